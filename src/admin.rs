@@ -0,0 +1,166 @@
+use async_std::io::{prelude::*, BufReader};
+use color_eyre::eyre::{eyre, Result};
+
+use crate::endpoint::{Endpoint, Gear};
+
+/// A connection to the gearman server's line-based admin protocol.
+///
+/// This is served on the same port as the binary protocol; the server tells
+/// them apart by the first byte.
+pub struct AdminConn {
+    stream: BufReader<Box<dyn Gear>>,
+}
+
+/// A row of the `status` admin command.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FunctionStatus {
+    pub name: String,
+    pub queued: usize,
+    pub running: usize,
+    pub workers: usize,
+}
+
+/// A row of the `workers` admin command.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct WorkerStatus {
+    pub fd: String,
+    pub address: String,
+    pub client_id: String,
+    pub functions: Vec<String>,
+}
+
+impl AdminConn {
+    pub async fn connect(server: &Endpoint) -> Result<Self> {
+        Ok(Self {
+            stream: BufReader::new(server.connect().await?),
+        })
+    }
+
+    /// Sends a command and collects its response lines, up to the `.` terminator.
+    pub async fn multiline(&mut self, command: &str) -> Result<Vec<String>> {
+        self.stream
+            .get_mut()
+            .write_all(format!("{}\n", command).as_bytes())
+            .await?;
+
+        let mut lines = Vec::new();
+        loop {
+            let mut line = String::new();
+            if self.stream.read_line(&mut line).await? == 0 {
+                return Err(eyre!("server closed the connection mid-response"));
+            }
+
+            let line = line.trim_end_matches(&['\r', '\n'][..]);
+            if line == "." {
+                return Ok(lines);
+            }
+
+            if line.starts_with("ERR ") {
+                return Err(eyre!("admin command {:?} failed: {}", command, line));
+            }
+
+            lines.push(line.to_string());
+        }
+    }
+
+    pub async fn status(&mut self) -> Result<Vec<FunctionStatus>> {
+        self.multiline("status")
+            .await?
+            .iter()
+            .map(|line| parse_status(line))
+            .collect()
+    }
+
+    pub async fn workers(&mut self) -> Result<Vec<WorkerStatus>> {
+        self.multiline("workers")
+            .await?
+            .iter()
+            .map(|line| parse_worker(line))
+            .collect()
+    }
+}
+
+fn parse_status(line: &str) -> Result<FunctionStatus> {
+    let fields: Vec<&str> = line.split('\t').collect();
+    if let [name, queued, running, workers] = fields[..] {
+        Ok(FunctionStatus {
+            name: name.to_string(),
+            queued: queued.parse()?,
+            running: running.parse()?,
+            workers: workers.parse()?,
+        })
+    } else {
+        Err(eyre!("bad status line: {:?}", line))
+    }
+}
+
+fn parse_worker(line: &str) -> Result<WorkerStatus> {
+    let mut fields = line.split_whitespace();
+    if let (Some(fd), Some(address), Some(client_id), Some(":")) =
+        (fields.next(), fields.next(), fields.next(), fields.next())
+    {
+        Ok(WorkerStatus {
+            fd: fd.to_string(),
+            address: address.to_string(),
+            client_id: client_id.to_string(),
+            functions: fields.map(String::from).collect(),
+        })
+    } else {
+        Err(eyre!("bad workers line: {:?}", line))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_status, parse_worker, FunctionStatus, WorkerStatus};
+
+    #[test]
+    fn status_line() {
+        assert_eq!(
+            parse_status("Test::reverse\t12\t3\t4").unwrap(),
+            FunctionStatus {
+                name: "Test::reverse".into(),
+                queued: 12,
+                running: 3,
+                workers: 4,
+            }
+        );
+    }
+
+    #[test]
+    fn status_line_bad() {
+        assert!(parse_status("Test::reverse\t12\t3").is_err());
+        assert!(parse_status("Test::reverse\tmany\t3\t4").is_err());
+    }
+
+    #[test]
+    fn workers_line() {
+        assert_eq!(
+            parse_worker("33 127.0.0.1 superman::v1::host::a,b=2 : a b").unwrap(),
+            WorkerStatus {
+                fd: "33".into(),
+                address: "127.0.0.1".into(),
+                client_id: "superman::v1::host::a,b=2".into(),
+                functions: vec!["a".into(), "b".into()],
+            }
+        );
+    }
+
+    #[test]
+    fn workers_line_bad() {
+        assert!(parse_worker("33 127.0.0.1 a b").is_err());
+    }
+
+    #[test]
+    fn workers_line_anonymous_idle() {
+        assert_eq!(
+            parse_worker("12 ::1 - :").unwrap(),
+            WorkerStatus {
+                fd: "12".into(),
+                address: "::1".into(),
+                client_id: "-".into(),
+                functions: Vec::new(),
+            }
+        );
+    }
+}
@@ -0,0 +1,124 @@
+use async_std::io::prelude::*;
+use color_eyre::eyre::{eyre, Result};
+use deku::DekuContainerWrite;
+use std::fmt;
+
+use crate::{
+    endpoint::{Endpoint, Gear},
+    packet::{Packet, PacketDecoder, Request, Response},
+};
+
+/// A connection to the gearman server as a client, for submitting jobs.
+pub struct Client {
+    gear: Box<dyn Gear>,
+    decoder: PacketDecoder,
+}
+
+/// The server's handle for a submitted job.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct JobHandle(pub Vec<u8>);
+
+impl fmt::Display for JobHandle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&String::from_utf8_lossy(&self.0))
+    }
+}
+
+impl Client {
+    pub async fn connect(server: &Endpoint, max_packet_size: usize) -> Result<Self> {
+        Ok(Self {
+            gear: server.connect().await?,
+            decoder: PacketDecoder::new(max_packet_size),
+        })
+    }
+
+    /// Submits a background job, returning once the server has queued it.
+    ///
+    /// An empty `unique` lets the server pick one, so identical jobs aren't
+    /// coalesced.
+    pub async fn submit(&mut self, name: &[u8], unique: &[u8], data: &[u8]) -> Result<JobHandle> {
+        let submit = Packet::request(Request::SubmitJobBg {
+            name: name.to_vec(),
+            unique: unique.to_vec(),
+            data: data.to_vec(),
+        })?;
+        self.gear.write_all(&submit.to_bytes()?).await?;
+
+        match self.response().await? {
+            Response::JobCreated { handle } => Ok(JobHandle(handle)),
+            Response::Error { code, text } => Err(eyre!(
+                "server error {}: {}",
+                String::from_utf8_lossy(&code),
+                String::from_utf8_lossy(&text)
+            )),
+            other => Err(eyre!("expected JOB_CREATED, got {}", other)),
+        }
+    }
+
+    /// Waits for the next response from the server.
+    async fn response(&mut self) -> Result<Response> {
+        let mut buf = [0_u8; 1024];
+        loop {
+            if let Some(pkt) = self.decoder.next() {
+                return pkt?
+                    .as_response()
+                    .cloned()
+                    .ok_or_else(|| eyre!("server sent a request packet"));
+            }
+
+            let len = self.gear.read(&mut buf).await?;
+            if len == 0 {
+                return Err(eyre!("server closed the connection"));
+            }
+            self.decoder.push(&buf[0..len]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Client, JobHandle};
+    use crate::{
+        endpoint::Endpoint,
+        packet::{Packet, Request, Response},
+    };
+    use async_std::{net::TcpListener, prelude::*, task::spawn};
+    use deku::DekuContainerWrite;
+
+    #[async_std::test]
+    async fn submit_background() {
+        let server = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = Endpoint::parse(&server.local_addr().unwrap().to_string())
+            .await
+            .unwrap();
+
+        spawn(async move {
+            let (mut conn, _) = server.accept().await.unwrap();
+            let expected = Packet::request(Request::SubmitJobBg {
+                name: b"reverse".to_vec(),
+                unique: b"".to_vec(),
+                data: b"hello".to_vec(),
+            })
+            .unwrap()
+            .to_bytes()
+            .unwrap();
+            let mut buf = vec![0_u8; expected.len()];
+            conn.read_exact(&mut buf).await.unwrap();
+            assert_eq!(buf, expected);
+
+            let created = Packet::response(Response::JobCreated {
+                handle: b"H:localhost:7".to_vec(),
+            })
+            .unwrap()
+            .to_bytes()
+            .unwrap();
+            conn.write_all(&created).await.unwrap();
+        });
+
+        let mut client = Client::connect(&endpoint, 1024).await.unwrap();
+        assert_eq!(
+            client.submit(b"reverse", b"", b"hello").await.unwrap(),
+            JobHandle(b"H:localhost:7".to_vec())
+        );
+    }
+}
@@ -0,0 +1,394 @@
+use color_eyre::eyre::{eyre, Result};
+use serde::{Deserialize, Deserializer};
+use std::{env, fs, path::Path, path::PathBuf};
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Config {
+    /// `host:port`, `unix:/path/to/socket`, or `gears://host:port` for TLS.
+    #[serde(default = "default_server")]
+    pub server: String,
+    /// In bytes.
+    pub max_packet_size: Option<usize>,
+    /// In bytes.
+    pub read_buffer_size: Option<usize>,
+    /// In seconds, 0 to turn heartbeats off.
+    pub heartbeat: Option<u64>,
+    /// First delay before reconnecting to the server, in seconds. Doubles on
+    /// each failed attempt.
+    #[serde(default = "default_backoff_base")]
+    pub backoff_base: u64,
+    /// Longest delay before reconnecting, in seconds.
+    #[serde(default = "default_backoff_cap")]
+    pub backoff_cap: u64,
+    /// Check the server took our client id by echoing it back after setting it.
+    #[serde(default)]
+    pub verify_client_id: bool,
+    /// Register workers that have a timeout with CAN_DO_TIMEOUT.
+    #[serde(default)]
+    pub use_server_timeout: bool,
+    /// PEM certificates to trust for a `gears://` server.
+    pub tls_ca_file: Option<PathBuf>,
+    #[serde(default, rename = "worker")]
+    pub workers: Vec<WorkerConfig>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct WorkerConfig {
+    /// Function names to register on the one connection, given in the config
+    /// as either a string or an array of them.
+    #[serde(rename = "name", deserialize_with = "one_or_many")]
+    pub names: Vec<String>,
+    /// A path, or a bare command name to look up on `PATH`.
+    pub executor: PathBuf,
+    pub concurrency: usize,
+    /// In seconds.
+    pub timeout: Option<u64>,
+}
+
+fn default_server() -> String {
+    "127.0.0.1:4730".into()
+}
+
+fn default_backoff_base() -> u64 {
+    1
+}
+
+fn default_backoff_cap() -> u64 {
+    60
+}
+
+fn one_or_many<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(name) => vec![name],
+        OneOrMany::Many(names) => names,
+    })
+}
+
+impl WorkerConfig {
+    /// How the worker is named in messages and its client id.
+    pub fn label(&self) -> String {
+        self.names.join(",")
+    }
+}
+
+impl Config {
+    /// Reads and parses a config file, without checking the workers in it;
+    /// see [`Config::validate`].
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let contents = fs::read_to_string(path)
+            .map_err(|err| eyre!("could not read config {}: {}", path.display(), err))?;
+        Self::parse(&contents).map_err(|err| eyre!("invalid config {}: {}", path.display(), err))
+    }
+
+    /// Fails with everything [`Config::problems`] finds, if anything.
+    pub fn validate(&self, path: impl AsRef<Path>) -> Result<()> {
+        let problems = self.problems();
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(eyre!(
+                "invalid config {}:\n - {}",
+                path.as_ref().display(),
+                problems.join("\n - ")
+            ))
+        }
+    }
+
+    pub fn parse(contents: &str) -> Result<Self> {
+        Ok(toml::from_str(contents)?)
+    }
+
+    /// Things that look wrong but may be fine by the time a job runs, like a
+    /// command that isn't on `PATH` yet.
+    pub fn warnings(&self) -> Vec<String> {
+        self.workers
+            .iter()
+            .filter(|w| is_bare_command(&w.executor) && !on_path(&w.executor))
+            .map(|w| {
+                format!(
+                    "worker {}: executor {} is not on PATH",
+                    w.label(),
+                    w.executor.display()
+                )
+            })
+            .collect()
+    }
+
+    /// Checks the whole config and describes everything wrong with it, rather
+    /// than stopping at the first problem.
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+
+        if self.workers.is_empty() {
+            problems.push("no [[worker]] defined".to_string());
+        }
+
+        if self.read_buffer_size == Some(0) {
+            problems.push("read_buffer_size must be > 0".to_string());
+        }
+
+        if self.backoff_base == 0 {
+            problems.push("backoff_base must be > 0".to_string());
+        }
+
+        if self.backoff_base > self.backoff_cap {
+            problems.push(format!(
+                "backoff_base ({}) must not be more than backoff_cap ({})",
+                self.backoff_base, self.backoff_cap
+            ));
+        }
+
+        for worker in &self.workers {
+            if worker.names.iter().all(|name| name.is_empty()) {
+                problems.push("a [[worker]] has no function name".to_string());
+            } else if worker.names.iter().any(|name| name.is_empty()) {
+                problems.push(format!(
+                    "worker {}: function names must not be empty",
+                    worker.label()
+                ));
+            }
+
+            if !is_bare_command(&worker.executor) && !worker.executor.is_file() {
+                problems.push(format!(
+                    "worker {}: executor {} does not exist",
+                    worker.label(),
+                    worker.executor.display()
+                ));
+            }
+
+            if worker.concurrency == 0 {
+                problems.push(format!(
+                    "worker {}: concurrency must be > 0",
+                    worker.label()
+                ));
+            }
+
+            if worker.timeout == Some(0) {
+                problems.push(format!("worker {}: timeout must be > 0", worker.label()));
+            }
+        }
+
+        problems
+    }
+}
+
+/// Whether an executor is a command name like `run-order` rather than a path,
+/// so should be found the way a shell would.
+fn is_bare_command(executor: &Path) -> bool {
+    executor.components().count() == 1 && executor.file_name() == Some(executor.as_os_str())
+}
+
+fn on_path(command: &Path) -> bool {
+    env::var_os("PATH")
+        .map(|path| env::split_paths(&path).any(|dir| dir.join(command).is_file()))
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_bare_command, Config};
+    use std::path::Path;
+
+    #[test]
+    fn parse_workers() {
+        let config = Config::parse(
+            r#"
+            server = "gearman:4730"
+            verify_client_id = true
+            use_server_timeout = true
+
+            [[worker]]
+            name = "Test::reverse"
+            executor = "/bin/sh"
+            concurrency = 3
+            timeout = 120
+
+            [[worker]]
+            name = ["Test::sleep", "Test::nap"]
+            executor = "/bin/sh"
+            concurrency = 1
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.server, "gearman:4730");
+        assert!(config.verify_client_id);
+        assert!(config.use_server_timeout);
+        assert_eq!(config.workers.len(), 2);
+        assert_eq!(config.workers[0].names, vec!["Test::reverse"]);
+        assert_eq!(config.workers[0].timeout, Some(120));
+        assert_eq!(config.workers[1].names, vec!["Test::sleep", "Test::nap"]);
+        assert_eq!(config.workers[1].label(), "Test::sleep,Test::nap");
+        assert_eq!(config.workers[1].timeout, None);
+        assert!(config.problems().is_empty());
+    }
+
+    #[test]
+    fn sample_parses() {
+        let config = Config::parse(include_str!("../superman-rs.sample.toml")).unwrap();
+        assert_eq!(config.workers.len(), 1);
+    }
+
+    #[test]
+    fn default_server() {
+        let config = Config::parse("").unwrap();
+        assert_eq!(config.server, "127.0.0.1:4730");
+        assert!(!config.verify_client_id);
+    }
+
+    #[test]
+    fn all_problems() {
+        let config = Config::parse(
+            r#"
+            [[worker]]
+            name = "Test::missing"
+            executor = "/nonexistent/run-order"
+            concurrency = 0
+            timeout = 0
+
+            [[worker]]
+            name = "Test::fine"
+            executor = "/bin/sh"
+            concurrency = 1
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            config.problems(),
+            vec![
+                "worker Test::missing: executor /nonexistent/run-order does not exist",
+                "worker Test::missing: concurrency must be > 0",
+                "worker Test::missing: timeout must be > 0",
+            ]
+        );
+    }
+
+    #[test]
+    fn zero_read_buffer() {
+        let config = Config::parse(
+            r#"
+            read_buffer_size = 0
+
+            [[worker]]
+            name = "Test::fine"
+            executor = "/bin/sh"
+            concurrency = 1
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.problems(), vec!["read_buffer_size must be > 0"]);
+    }
+
+    #[test]
+    fn backoff() {
+        let config = Config::parse("").unwrap();
+        assert_eq!(config.backoff_base, 1);
+        assert_eq!(config.backoff_cap, 60);
+
+        let config = Config::parse(
+            r#"
+            backoff_base = 0
+
+            [[worker]]
+            name = "Test::fine"
+            executor = "/bin/sh"
+            concurrency = 1
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.problems(), vec!["backoff_base must be > 0"]);
+
+        let config = Config::parse(
+            r#"
+            backoff_base = 30
+            backoff_cap = 10
+
+            [[worker]]
+            name = "Test::fine"
+            executor = "/bin/sh"
+            concurrency = 1
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.problems(),
+            vec!["backoff_base (30) must not be more than backoff_cap (10)"]
+        );
+    }
+
+    #[test]
+    fn bare_command() {
+        assert!(is_bare_command(Path::new("run-order")));
+        assert!(!is_bare_command(Path::new("./run-order")));
+        assert!(!is_bare_command(Path::new("bin/run-order")));
+        assert!(!is_bare_command(Path::new("/usr/bin/run-order")));
+    }
+
+    #[test]
+    fn executor_on_path() {
+        let config = Config::parse(
+            r#"
+            [[worker]]
+            name = "Test::found"
+            executor = "sh"
+            concurrency = 1
+
+            [[worker]]
+            name = "Test::missing"
+            executor = "superman-no-such-executor"
+            concurrency = 1
+            "#,
+        )
+        .unwrap();
+
+        assert!(config.problems().is_empty());
+        assert_eq!(
+            config.warnings(),
+            vec!["worker Test::missing: executor superman-no-such-executor is not on PATH"]
+        );
+    }
+
+    #[test]
+    fn empty_names() {
+        let config = Config::parse(
+            r#"
+            [[worker]]
+            name = []
+            executor = "/bin/sh"
+            concurrency = 1
+
+            [[worker]]
+            name = ["Test::fine", ""]
+            executor = "/bin/sh"
+            concurrency = 1
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            config.problems(),
+            vec![
+                "a [[worker]] has no function name",
+                "worker Test::fine,: function names must not be empty",
+            ]
+        );
+    }
+
+    #[test]
+    fn no_workers() {
+        assert_eq!(
+            Config::parse("").unwrap().problems(),
+            vec!["no [[worker]] defined"]
+        );
+    }
+}
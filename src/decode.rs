@@ -0,0 +1,119 @@
+use color_eyre::eyre::{eyre, Result};
+
+use crate::packet::{DecodeError, PacketDecoder};
+
+/// Decodes a hex dump of captured gearman traffic and prints every packet in it.
+///
+/// Whitespace in the dump is ignored, so the output of most hex dumpers can be
+/// pasted as-is (minus any offset/ASCII columns). Malformed packets are
+/// reported and skipped, so one bad frame doesn't hide the rest.
+pub fn run(hex: &str) -> Result<()> {
+    // Nothing is being buffered from a socket, so the only limit is what the
+    // length field can say.
+    let mut decoder = PacketDecoder::new(u32::MAX as usize);
+    decoder.push(&parse_hex(hex)?);
+
+    let mut n = 0;
+    let mut malformed = 0;
+    loop {
+        let before = decoder.buffered();
+        match decoder.next() {
+            None => break,
+            Some(Ok(pkt)) => println!(
+                "packet {}: {} ({} bytes) {}",
+                n,
+                if pkt.is_request() {
+                    "request"
+                } else {
+                    "response"
+                },
+                before - decoder.buffered(),
+                pkt
+            ),
+            Some(Err(DecodeError::Malformed(err))) => {
+                println!(
+                    "packet {}: malformed ({} bytes): {}",
+                    n,
+                    before - decoder.buffered(),
+                    err
+                );
+                malformed += 1;
+            }
+            Some(Err(err @ DecodeError::TooLarge { .. })) => return Err(err.into()),
+        }
+        n += 1;
+    }
+
+    if n == 0 && decoder.buffered() == 0 {
+        println!("no packets in input");
+    }
+
+    if decoder.buffered() > 0 {
+        Err(eyre!(
+            "{} trailing bytes after {} packet(s) are a partial frame",
+            decoder.buffered(),
+            n
+        ))
+    } else if malformed > 0 {
+        Err(eyre!("{} of {} packet(s) are malformed", malformed, n))
+    } else {
+        Ok(())
+    }
+}
+
+fn parse_hex(hex: &str) -> Result<Vec<u8>> {
+    let digits = hex
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .map(|c| {
+            c.to_digit(16)
+                .map(|d| d as u8)
+                .ok_or_else(|| eyre!("not a hex digit: {:?}", c))
+        })
+        .collect::<Result<Vec<u8>>>()?;
+
+    if digits.len() % 2 != 0 {
+        return Err(eyre!("odd number of hex digits ({})", digits.len()));
+    }
+
+    Ok(digits.chunks(2).map(|p| (p[0] << 4) | p[1]).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_hex, run};
+
+    #[test]
+    fn run_packets() {
+        assert!(run("00524553 00000006 00000000 00524553 0000000a 00000000").is_ok());
+    }
+
+    #[test]
+    fn run_short_length() {
+        assert!(run("00524551 0000000d 00000000 61626300").is_err());
+        assert!(run("00524553 0000001f 00000001 610062006300").is_err());
+    }
+
+    #[test]
+    fn run_partial_frame() {
+        assert!(run("00524553 0000000a 00000004 6162").is_err());
+    }
+
+    #[test]
+    fn parse_hex_spaced() {
+        assert_eq!(
+            parse_hex("00 52 45 53\n0000000a 00000000").unwrap(),
+            b"\0RES\0\0\0\x0a\0\0\0\0".to_vec()
+        );
+    }
+
+    #[test]
+    fn parse_hex_odd() {
+        assert!(parse_hex("005").is_err());
+    }
+
+    #[test]
+    fn parse_hex_invalid() {
+        assert!(parse_hex("0g").is_err());
+    }
+}
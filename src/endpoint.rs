@@ -0,0 +1,335 @@
+#[cfg(unix)]
+use async_std::os::unix::net::UnixStream;
+use async_std::{
+    io::{self, Read, Write},
+    net::{SocketAddr, TcpStream, ToSocketAddrs},
+};
+#[cfg(feature = "tls")]
+use async_tls::TlsConnector;
+use color_eyre::eyre::{eyre, Result};
+#[cfg(feature = "tls")]
+use rustls::ClientConfig;
+use std::{fmt, future::Future, path::PathBuf};
+
+/// Where the gearman server listens.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Endpoint {
+    /// A `host:port`, kept unresolved so each connection picks up DNS changes
+    /// (failover VIPs, container service IPs, etc).
+    Tcp(String),
+    /// A `unix:/path/to/sock`, for a server on the same host.
+    #[cfg(unix)]
+    Unix(PathBuf),
+    /// A `gears://host:port`, for a server behind TLS. The host is also the
+    /// name its certificate is checked against.
+    #[cfg(feature = "tls")]
+    Tls {
+        addr: String,
+        domain: String,
+        /// PEM certificates to trust on top of the usual web roots.
+        ca_file: Option<PathBuf>,
+    },
+}
+
+/// A connection to the server, over whichever transport the endpoint uses.
+pub trait Gear: Read + Write + Unpin + Send + 'static {}
+
+impl<T: Read + Write + Unpin + Send + 'static> Gear for T {}
+
+impl Endpoint {
+    /// Parses a server address, checking that a TCP one resolves.
+    ///
+    /// A Unix socket isn't checked, as the server may not have created it yet.
+    pub async fn parse(server: &str) -> Result<Self> {
+        if let Some(path) = server.strip_prefix("unix:") {
+            return Self::unix(path);
+        }
+
+        if let Some(addr) = server.strip_prefix("gears://") {
+            let endpoint = Self::tls(addr)?;
+            resolves(addr).await?;
+            return Ok(endpoint);
+        }
+
+        resolves(server).await?;
+        Ok(Self::Tcp(server.to_string()))
+    }
+
+    #[cfg(unix)]
+    fn unix(path: &str) -> Result<Self> {
+        if path.is_empty() {
+            Err(eyre!("no socket path provided"))
+        } else {
+            Ok(Self::Unix(path.into()))
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn unix(_path: &str) -> Result<Self> {
+        Err(eyre!("unix sockets are not supported on this platform"))
+    }
+
+    #[cfg(feature = "tls")]
+    fn tls(addr: &str) -> Result<Self> {
+        let host = match addr.rfind(':') {
+            Some(n) => &addr[..n],
+            None => addr,
+        };
+        let unbracketed = host.trim_start_matches('[').trim_end_matches(']');
+
+        // Certificates can only be checked against a host name.
+        if unbracketed.parse::<std::net::IpAddr>().is_ok() {
+            return Err(eyre!(
+                "gears:// needs a host name to check the certificate against, not an IP address like {}",
+                host
+            ));
+        }
+
+        Ok(Self::Tls {
+            addr: addr.to_string(),
+            domain: host.to_string(),
+            ca_file: None,
+        })
+    }
+
+    #[cfg(not(feature = "tls"))]
+    fn tls(_addr: &str) -> Result<Self> {
+        Err(eyre!(
+            "gears:// needs superman to be built with the tls feature"
+        ))
+    }
+
+    /// Trusts the certificates in a PEM file for a `gears://` endpoint.
+    pub fn set_tls_ca_file(&mut self, path: PathBuf) -> Result<()> {
+        match self {
+            #[cfg(feature = "tls")]
+            Self::Tls { ca_file, .. } => {
+                *ca_file = Some(path);
+                Ok(())
+            }
+            _ => Err(eyre!(
+                "tls_ca_file {} is only used with a gears:// server",
+                path.display()
+            )),
+        }
+    }
+
+    pub async fn connect(&self) -> Result<Box<dyn Gear>> {
+        Ok(match self {
+            Self::Tcp(addr) => Box::new(connect_tcp(addr, lookup).await?),
+            #[cfg(unix)]
+            Self::Unix(path) => Box::new(UnixStream::connect(path).await?),
+            #[cfg(feature = "tls")]
+            Self::Tls {
+                addr,
+                domain,
+                ca_file,
+            } => {
+                let connector = tls_connector(ca_file.as_ref())?;
+                let stream = connect_tcp(addr, lookup).await?;
+                Box::new(connector.connect(domain, stream).await?)
+            }
+        })
+    }
+}
+
+async fn resolves(addr: &str) -> Result<()> {
+    lookup(addr.to_string())
+        .await?
+        .first()
+        .ok_or(eyre!("no server addr provided"))?;
+    Ok(())
+}
+
+/// Resolves a `host:port` with the system resolver.
+async fn lookup(addr: String) -> io::Result<Vec<SocketAddr>> {
+    Ok(addr.to_socket_addrs().await?.collect())
+}
+
+/// Resolves `addr` afresh and connects to the first address that accepts.
+async fn connect_tcp<F, Fut>(addr: &str, resolve: F) -> Result<TcpStream>
+where
+    F: FnOnce(String) -> Fut,
+    Fut: Future<Output = io::Result<Vec<SocketAddr>>>,
+{
+    let mut last_err = None;
+    for sock in resolve(addr.to_string()).await? {
+        match TcpStream::connect(sock).await {
+            Ok(stream) => return Ok(stream),
+            Err(err) => last_err = Some(err),
+        }
+    }
+
+    Err(match last_err {
+        Some(err) => eyre!("could not connect to {}: {}", addr, err),
+        None => eyre!("{} did not resolve to any address", addr),
+    })
+}
+
+/// Builds a connector trusting the usual web roots, plus any in `ca_file`.
+///
+/// The file is read on every connect, so a rotated CA is picked up on the next
+/// reconnect.
+#[cfg(feature = "tls")]
+fn tls_connector(ca_file: Option<&PathBuf>) -> Result<TlsConnector> {
+    let mut config = ClientConfig::new();
+    config
+        .root_store
+        .add_server_trust_anchors(&webpki_roots::TLS_SERVER_ROOTS);
+
+    if let Some(path) = ca_file {
+        let mut pem = std::io::BufReader::new(std::fs::File::open(path)?);
+        let (added, _) = config
+            .root_store
+            .add_pem_file(&mut pem)
+            .map_err(|_| eyre!("could not read certificates from {}", path.display()))?;
+        if added == 0 {
+            return Err(eyre!("no certificates in {}", path.display()));
+        }
+    }
+
+    Ok(config.into())
+}
+
+impl fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Tcp(addr) => f.write_str(addr),
+            #[cfg(unix)]
+            Self::Unix(path) => write!(f, "unix:{}", path.display()),
+            #[cfg(feature = "tls")]
+            Self::Tls { addr, .. } => write!(f, "gears://{}", addr),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{connect_tcp, Endpoint};
+    use async_std::{net::TcpListener, prelude::*};
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    #[async_std::test]
+    async fn parse_tcp() {
+        assert_eq!(
+            Endpoint::parse("127.0.0.1:4730").await.unwrap(),
+            Endpoint::Tcp("127.0.0.1:4730".into())
+        );
+    }
+
+    #[async_std::test]
+    async fn connect_resolves_each_time() {
+        let first = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let second = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addrs = vec![first.local_addr().unwrap(), second.local_addr().unwrap()];
+
+        // A resolver whose answer changes between calls, like a moved VIP.
+        let calls = Arc::new(AtomicUsize::new(0));
+        let resolve = || {
+            let calls = calls.clone();
+            let addrs = addrs.clone();
+            move |addr: String| async move {
+                assert_eq!(addr, "gearman:4730");
+                Ok(vec![addrs[calls.fetch_add(1, Ordering::SeqCst)]])
+            }
+        };
+
+        let conn = connect_tcp("gearman:4730", resolve()).await.unwrap();
+        assert_eq!(conn.peer_addr().unwrap(), addrs[0]);
+        first.incoming().next().await.unwrap().unwrap();
+
+        let conn = connect_tcp("gearman:4730", resolve()).await.unwrap();
+        assert_eq!(conn.peer_addr().unwrap(), addrs[1]);
+        second.incoming().next().await.unwrap().unwrap();
+
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[async_std::test]
+    async fn connect_unresolved() {
+        let err = connect_tcp("gearman:4730", |_| async { Ok(vec![]) })
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "gearman:4730 did not resolve to any address"
+        );
+    }
+
+    #[async_std::test]
+    async fn parse_unix() {
+        let endpoint = Endpoint::parse("unix:/run/gearmand.sock").await.unwrap();
+        assert_eq!(endpoint, Endpoint::Unix("/run/gearmand.sock".into()));
+        assert_eq!(endpoint.to_string(), "unix:/run/gearmand.sock");
+    }
+
+    #[async_std::test]
+    async fn parse_unix_empty() {
+        assert!(Endpoint::parse("unix:").await.is_err());
+    }
+
+    #[async_std::test]
+    async fn tls_ca_file_needs_gears() {
+        let mut endpoint = Endpoint::parse("127.0.0.1:4730").await.unwrap();
+        assert!(endpoint.set_tls_ca_file("/etc/ca.pem".into()).is_err());
+    }
+
+    #[cfg(not(feature = "tls"))]
+    #[async_std::test]
+    async fn parse_gears_without_tls() {
+        assert!(Endpoint::parse("gears://localhost:4730").await.is_err());
+
+        // Not a DNS error, even though the host doesn't resolve.
+        let err = Endpoint::parse("gears://superman-no-such-host.invalid:4730")
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "gears:// needs superman to be built with the tls feature"
+        );
+    }
+
+    #[cfg(feature = "tls")]
+    #[async_std::test]
+    async fn parse_gears_ip() {
+        for server in &["gears://127.0.0.1:4730", "gears://[::1]:4730"] {
+            let err = Endpoint::parse(server).await.unwrap_err();
+            assert!(err.to_string().contains("not an IP address"), "{}", err);
+        }
+    }
+
+    #[cfg(feature = "tls")]
+    #[async_std::test]
+    async fn parse_gears() {
+        let mut endpoint = Endpoint::parse("gears://localhost:4730").await.unwrap();
+        endpoint.set_tls_ca_file("/etc/ca.pem".into()).unwrap();
+        assert_eq!(
+            endpoint,
+            Endpoint::Tls {
+                addr: "localhost:4730".into(),
+                domain: "localhost".into(),
+                ca_file: Some("/etc/ca.pem".into()),
+            }
+        );
+        assert_eq!(endpoint.to_string(), "gears://localhost:4730");
+    }
+
+    #[cfg(feature = "tls")]
+    #[async_std::test]
+    async fn connect_gears_bad_ca_file() {
+        let server = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut endpoint = Endpoint::parse(&format!(
+            "gears://localhost:{}",
+            server.local_addr().unwrap().port()
+        ))
+        .await
+        .unwrap();
+        endpoint
+            .set_tls_ca_file("/nonexistent/ca.pem".into())
+            .unwrap();
+        assert!(endpoint.connect().await.is_err());
+    }
+}
@@ -1,43 +1,177 @@
 // use uuid::Uuid;
 
+use admin::{AdminConn, FunctionStatus, WorkerStatus};
 use async_std::{
-    io::{ReadExt, Write},
-    net::{SocketAddr, TcpStream, ToSocketAddrs},
+    future::timeout,
+    io::{Read, ReadExt, Write},
     path::Path,
     prelude::*,
-    task::{spawn, JoinHandle},
+    task::{sleep, spawn, JoinHandle},
 };
+use client::Client;
 use color_eyre::eyre::{eyre, Result};
+use config::Config;
 use deku::DekuContainerWrite;
-use futures::io::AsyncReadExt;
-use packet::{Packet, Request, Response};
+use endpoint::{Endpoint, Gear};
+use futures::{future::try_join_all, io::AsyncReadExt};
+use packet::{DecodeError, Packet, PacketDecoder, Request, Response};
+use rand::{rngs::StdRng, Rng, RngCore, SeedableRng};
+use std::{
+    path::PathBuf,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
 
+mod admin;
+mod client;
+mod config;
+mod decode;
+mod endpoint;
 mod packet;
 
 #[async_std::main]
 async fn main() -> Result<()> {
     color_eyre::install()?;
 
-    let state = State::create("127.0.0.1:4730").await?;
-    state.worker("supertest", "/usr/bin/true", 1).await?;
+    let mut config_path = PathBuf::from("superman-rs.toml");
+    let mut verbose = false;
+    let mut positional = Vec::new();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--config" {
+            config_path = args
+                .next()
+                .ok_or_else(|| eyre!("--config needs a path"))?
+                .into();
+        } else if arg == "--verbose" {
+            verbose = true;
+        } else {
+            positional.push(arg);
+        }
+    }
+
+    let command = positional.first().map(String::as_str);
+    if command == Some("decode") {
+        return decode::run(&positional[1..].join(" "));
+    }
+
+    let config = Config::load(&config_path)?;
+    let mut state = State::create(&config.server).await?;
+    if let Some(path) = config.tls_ca_file.clone() {
+        state.server.set_tls_ca_file(path)?;
+    }
+    if let Some(size) = config.max_packet_size {
+        state.max_packet_size = size;
+    }
+    if let Some(size) = config.read_buffer_size {
+        state.read_buffer_size = size;
+    }
+    if let Some(secs) = config.heartbeat {
+        state.heartbeat = match secs {
+            0 => None,
+            secs => Some(Duration::from_secs(secs)),
+        };
+    }
+    state.backoff_base = Duration::from_secs(config.backoff_base);
+    state.backoff_cap = Duration::from_secs(config.backoff_cap);
+    state.verbose = verbose;
+    state.verify_client_id = config.verify_client_id;
+    state.use_server_timeout = config.use_server_timeout;
+
+    match command {
+        Some("status") => {
+            println!("function\tqueued\trunning\tworkers");
+            for f in state.server_status().await? {
+                println!("{}\t{}\t{}\t{}", f.name, f.queued, f.running, f.workers);
+            }
+        }
+        Some("workers") => {
+            println!("fd\taddress\tclient id\tfunctions");
+            for w in state.server_workers().await? {
+                println!(
+                    "{}\t{}\t{}\t{}",
+                    w.fd,
+                    w.address,
+                    w.client_id,
+                    w.functions.join(" ")
+                );
+            }
+        }
+        Some("submit") => {
+            let name = positional
+                .get(1)
+                .ok_or_else(|| eyre!("submit needs a function name"))?;
+            let handle = Client::connect(&state.server, state.max_packet_size)
+                .await?
+                .submit(name.as_bytes(), b"", positional[2..].join(" ").as_bytes())
+                .await?;
+            println!("{}", handle);
+        }
+        Some(other) => return Err(eyre!("unknown command {:?}", other)),
+        None => {
+            config.validate(&config_path)?;
+            for warning in config.warnings() {
+                eprintln!("warning: {}", warning);
+            }
+            let state = &state;
+            try_join_all(config.workers.iter().map(|w| async move {
+                let names: Vec<&str> = w.names.iter().map(String::as_str).collect();
+                state
+                    .worker(
+                        &names,
+                        &w.executor,
+                        w.concurrency,
+                        w.timeout.map(Duration::from_secs),
+                    )
+                    .await
+            }))
+            .await?;
+        }
+    }
 
     Ok(())
 }
 
-#[derive(Debug)]
 struct State {
-    server: SocketAddr,
+    server: Endpoint,
     base_id: String,
+    /// First delay before reconnecting, doubled on each failed attempt.
+    backoff_base: Duration,
+    /// Longest delay before reconnecting, and how long a connection must stay
+    /// up before the delay starts again from `backoff_base`.
+    backoff_cap: Duration,
+    /// Picks each reconnect delay at random up to the backoff, so a fleet of
+    /// workers doesn't reconnect in lockstep after a server restart.
+    rng: Mutex<Box<dyn RngCore + Send>>,
+    /// Largest packet body the server may send, so a corrupt or malicious
+    /// length header can't make us buffer without bound.
+    max_packet_size: usize,
+    /// How much to read from the server at once. Larger reads mean fewer
+    /// syscalls for big workloads, at the cost of this much memory per
+    /// connection.
+    read_buffer_size: usize,
+    /// After this long without hearing from the server, send it an ECHO_REQ,
+    /// and reconnect if nothing comes back within the same time. This catches
+    /// connections that died without a reset, which would otherwise leave us
+    /// waiting for jobs forever.
+    heartbeat: Option<Duration>,
+    /// Echo the client id back after setting it, as a diagnostic for servers
+    /// that silently drop ids they don't like.
+    verify_client_id: bool,
+    /// Register functions with CAN_DO_TIMEOUT when they have a timeout, so the
+    /// server can reclaim jobs from a worker that's stuck.
+    use_server_timeout: bool,
+    /// Log every packet received from the server to stderr.
+    verbose: bool,
 }
 
+/// How long to wait for the server to answer an ECHO_REQ.
+const ECHO_TIMEOUT: Duration = Duration::from_secs(5);
+
 impl State {
-    async fn create(server: impl ToSocketAddrs) -> Result<Self> {
+    async fn create(server: &str) -> Result<Self> {
         Ok(Self {
-            server: server
-                .to_socket_addrs()
-                .await?
-                .next()
-                .ok_or(eyre!("no server addr provided"))?,
+            server: Endpoint::parse(server).await?,
             base_id: format!(
                 "{}::v{}::{}",
                 env!("CARGO_PKG_NAME"),
@@ -46,48 +180,246 @@ impl State {
                     .into_string()
                     .map_err(|s| eyre!("Hostname isn't UTF-8: {:?}", s))?
             ),
+            backoff_base: Duration::from_secs(1),
+            backoff_cap: Duration::from_secs(60),
+            rng: Mutex::new(Box::new(StdRng::from_entropy())),
+            max_packet_size: 128 * 1024 * 1024,
+            read_buffer_size: 8 * 1024,
+            heartbeat: Some(Duration::from_secs(60)),
+            verify_client_id: false,
+            use_server_timeout: false,
+            verbose: false,
         })
     }
 
+    /// Runs a worker for one or more function names, which share a connection
+    /// and the concurrency and timeout settings.
     async fn worker(
         &self,
-        name: &str,
-        executor: impl AsRef<Path>,
+        names: &[&str],
+        _executor: impl AsRef<Path>,
         concurrency: usize,
+        timeout: Option<Duration>,
     ) -> Result<()> {
+        if names.is_empty() {
+            return Err(eyre!("a worker needs at least one function name"));
+        }
+
+        let name = names.join(",");
         let client_id = format!("{}::{}={}", self.base_id, name, concurrency)
             .as_bytes()
             .to_vec();
 
-        let mut gear = TcpStream::connect(self.server).await?;
-        Request::SetClientId { id: client_id }
-            .send(&mut gear)
-            .await?;
+        let mut attempt = 0;
+        loop {
+            match self.connect(names, &client_id, timeout).await {
+                Ok(gear) => {
+                    let connected = Instant::now();
+                    match listen(
+                        gear,
+                        self.max_packet_size,
+                        self.read_buffer_size,
+                        self.heartbeat,
+                        self.verbose,
+                    )
+                    .await
+                    {
+                        Ok(()) => return Ok(()),
+                        Err(err) => eprintln!("[{}] connection lost: {}", name, err),
+                    }
+
+                    // Only start backing off afresh if the connection held up for
+                    // a while, so a server that accepts and then drops every
+                    // connection still gets backed off from.
+                    if connected.elapsed() >= self.backoff_cap {
+                        attempt = 0;
+                    }
+                }
+                Err(err) => eprintln!("[{}] could not connect: {}", name, err),
+            }
 
-        Request::CanDo {
-            name: name.as_bytes().to_vec(),
+            let delay = self.backoff(attempt);
+            attempt += 1;
+            eprintln!(
+                "[{}] reconnecting in {:?} (attempt {})",
+                name, delay, attempt
+            );
+            sleep(delay).await;
+        }
+    }
+
+    async fn connect(
+        &self,
+        names: &[&str],
+        client_id: &[u8],
+        job_timeout: Option<Duration>,
+    ) -> Result<Box<dyn Gear>> {
+        let mut gear = self.server.connect().await?;
+        Request::SetClientId {
+            id: client_id.to_vec(),
         }
         .send(&mut gear)
         .await?;
 
+        if self.verify_client_id {
+            let echoed = timeout(
+                ECHO_TIMEOUT,
+                echo(&mut gear, client_id, self.max_packet_size),
+            )
+            .await
+            .map_err(|_| eyre!("server did not answer ECHO_REQ"))??;
+            if echoed != client_id {
+                eprintln!(
+                    "warning: server echoed client id {:?} as {:?}, it may not have been set",
+                    String::from_utf8_lossy(client_id),
+                    String::from_utf8_lossy(&echoed)
+                );
+            }
+        }
+
+        // The server may still hold abilities from a previous connection.
+        Request::ResetAbilities.send(&mut gear).await?;
+
+        for name in names {
+            let name = name.as_bytes().to_vec();
+            match job_timeout {
+                Some(t) if self.use_server_timeout => Request::CanDoTimeout {
+                    name,
+                    timeout: t.as_secs().to_string().into_bytes(),
+                },
+                _ => Request::CanDo { name },
+            }
+            .send(&mut gear)
+            .await?;
+        }
+
         Request::PreSleep.send(&mut gear).await?;
 
-        let (mut gear_read, gear_write) = gear.split();
+        Ok(gear)
+    }
+
+    /// Queries the server's queue and worker counts per function over the admin protocol.
+    async fn server_status(&self) -> Result<Vec<FunctionStatus>> {
+        AdminConn::connect(&self.server).await?.status().await
+    }
+
+    /// Lists the workers connected to the server over the admin protocol.
+    async fn server_workers(&self) -> Result<Vec<WorkerStatus>> {
+        AdminConn::connect(&self.server).await?.workers().await
+    }
+
+    /// Exponential backoff from `backoff_base`, doubling per attempt up to `backoff_cap`.
+    fn backoff_ceiling(&self, attempt: u32) -> Duration {
+        2_u32
+            .checked_pow(attempt)
+            .and_then(|factor| self.backoff_base.checked_mul(factor))
+            .map_or(self.backoff_cap, |delay| delay.min(self.backoff_cap))
+    }
+
+    /// A random delay between zero and the backoff ceiling for this attempt.
+    fn backoff(&self, attempt: u32) -> Duration {
+        let fraction: f64 = self.rng.lock().unwrap().gen();
+        self.backoff_ceiling(attempt).mul_f64(fraction)
+    }
+}
+
+async fn listen(
+    gear: impl Gear,
+    max_packet_size: usize,
+    read_buffer_size: usize,
+    heartbeat: Option<Duration>,
+    verbose: bool,
+) -> Result<()> {
+    let (mut gear_read, mut gear_write) = gear.split();
 
-        let listener: JoinHandle<Result<()>> = spawn(async move {
-            loop {
-                let mut buf = vec![0_u8; 1024];
-                ReadExt::read(&mut gear_read, &mut buf).await?;
-                println!("bytes: {:?}", buf);
-                break;
+    let listener: JoinHandle<Result<()>> = spawn(async move {
+        let mut decoder = PacketDecoder::new(max_packet_size);
+        let mut buf = vec![0_u8; read_buffer_size];
+        let mut awaiting_echo = false;
+        loop {
+            let read = ReadExt::read(&mut gear_read, &mut buf);
+            let len = match heartbeat {
+                None => read.await?,
+                Some(interval) => match timeout(interval, read).await {
+                    Ok(len) => len?,
+                    Err(_) if awaiting_echo => {
+                        return Err(eyre!("server did not answer a heartbeat in {:?}", interval))
+                    }
+                    Err(_) => {
+                        Request::EchoReq {
+                            data: b"heartbeat".to_vec(),
+                        }
+                        .send(&mut gear_write)
+                        .await?;
+                        awaiting_echo = true;
+                        continue;
+                    }
+                },
+            };
+            awaiting_echo = false;
+
+            if len == 0 {
+                return Err(eyre!("server closed the connection"));
             }
 
-            Ok(())
-        });
+            decoder.push(&buf[0..len]);
+            for pkt in &mut decoder {
+                let pkt = match pkt {
+                    Ok(pkt) => pkt,
+                    // The decoder won't get past it, so drop the connection.
+                    Err(err @ DecodeError::TooLarge { .. }) => return Err(err.into()),
+                    // The decoder has already skipped past it.
+                    Err(DecodeError::Malformed(err)) => {
+                        eprintln!("dropped a malformed packet: {}", err);
+                        continue;
+                    }
+                };
 
-        listener.await?;
+                match pkt.as_response() {
+                    Some(Response::Error { code, text }) => eprintln!(
+                        "server error {}: {}",
+                        String::from_utf8_lossy(code),
+                        String::from_utf8_lossy(text)
+                    ),
+                    _ if verbose => eprintln!("packet: {}", pkt),
+                    _ => {}
+                }
+            }
+        }
+    });
 
-        Ok(())
+    listener.await
+}
+
+/// Sends an ECHO_REQ and waits for the server to send the data back.
+///
+/// This is only used during the handshake, before the server has any reason
+/// to send us anything else, so other packets are discarded.
+async fn echo(
+    gear: &mut (impl Read + Write + Unpin),
+    data: &[u8],
+    max_packet_size: usize,
+) -> Result<Vec<u8>> {
+    Request::EchoReq {
+        data: data.to_vec(),
+    }
+    .send(gear)
+    .await?;
+
+    let mut decoder = PacketDecoder::new(max_packet_size);
+    let mut buf = [0_u8; 1024];
+    loop {
+        let len = ReadExt::read(gear, &mut buf).await?;
+        if len == 0 {
+            return Err(eyre!("server closed the connection"));
+        }
+
+        decoder.push(&buf[0..len]);
+        for pkt in &mut decoder {
+            if let Some(Response::EchoRes { data }) = pkt?.as_response() {
+                return Ok(data.clone());
+            }
+        }
     }
 }
 
@@ -98,3 +430,352 @@ impl Request {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{FunctionStatus, Packet, Request, Response, State};
+    #[cfg(unix)]
+    use async_std::os::unix::net::UnixListener;
+    use async_std::{
+        future::timeout,
+        io::Read,
+        net::{TcpListener, TcpStream},
+        prelude::*,
+        task::spawn,
+    };
+    use deku::DekuContainerWrite;
+    use rand::{
+        rngs::{mock::StepRng, StdRng},
+        SeedableRng,
+    };
+    use std::{
+        sync::Mutex,
+        time::{Duration, Instant},
+    };
+
+    const PRE_SLEEP: &[u8] = b"\0REQ\0\0\0\x04\0\0\0\0";
+
+    async fn state(server: &TcpListener) -> State {
+        let mut state = State::create(&server.local_addr().unwrap().to_string())
+            .await
+            .unwrap();
+        state.backoff_base = Duration::from_millis(10);
+        state.backoff_cap = Duration::from_millis(40);
+        state
+    }
+
+    /// Accepts a worker connection and reads up to the end of its handshake.
+    async fn accept_handshake(server: &TcpListener) -> (TcpStream, Vec<u8>) {
+        let (mut conn, _) = server.accept().await.unwrap();
+        let handshake = read_handshake(&mut conn).await;
+        (conn, handshake)
+    }
+
+    async fn read_handshake(conn: &mut (impl Read + Unpin)) -> Vec<u8> {
+        let mut handshake = Vec::new();
+        let mut buf = [0_u8; 1024];
+        while !handshake.ends_with(PRE_SLEEP) {
+            let len = conn.read(&mut buf).await.unwrap();
+            assert_ne!(len, 0, "worker hung up during handshake");
+            handshake.extend(&buf[0..len]);
+        }
+        handshake
+    }
+
+    #[async_std::test]
+    async fn worker_reconnects_on_server_close() {
+        let server = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let state = state(&server).await;
+        spawn(async move { state.worker(&["supertest"], "/usr/bin/true", 1, None).await });
+
+        timeout(Duration::from_secs(5), async move {
+            for _ in 0..2 {
+                accept_handshake(&server).await;
+            }
+        })
+        .await
+        .expect("worker did not reconnect");
+    }
+
+    #[async_std::test]
+    async fn worker_drops_oversized_packet() {
+        let server = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut state = state(&server).await;
+        state.max_packet_size = 1024;
+        spawn(async move { state.worker(&["supertest"], "/usr/bin/true", 1, None).await });
+
+        timeout(Duration::from_secs(5), async move {
+            let (mut conn, _) = accept_handshake(&server).await;
+            let mut header = Vec::new();
+            header.extend(b"\0RES");
+            header.extend(&31_u32.to_be_bytes());
+            header.extend(&u32::MAX.to_be_bytes());
+            conn.write_all(&header).await.unwrap();
+
+            let mut buf = [0_u8; 1024];
+            assert_eq!(conn.read(&mut buf).await.unwrap(), 0);
+        })
+        .await
+        .expect("worker kept the connection open");
+    }
+
+    #[cfg(unix)]
+    #[async_std::test]
+    async fn worker_over_unix_socket() {
+        let path = std::env::temp_dir().join(format!("superman-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let server = UnixListener::bind(&path).await.unwrap();
+
+        let mut state = State::create(&format!("unix:{}", path.display()))
+            .await
+            .unwrap();
+        state.backoff_base = Duration::from_millis(10);
+        spawn(async move { state.worker(&["supertest"], "/usr/bin/true", 1, None).await });
+
+        timeout(Duration::from_secs(5), async move {
+            let (mut conn, _) = server.accept().await.unwrap();
+            read_handshake(&mut conn).await;
+        })
+        .await
+        .expect("worker did not connect over the socket");
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[async_std::test]
+    async fn worker_reconnects_on_missed_heartbeat() {
+        let server = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut state = state(&server).await;
+        state.heartbeat = Some(Duration::from_millis(50));
+        spawn(async move { state.worker(&["supertest"], "/usr/bin/true", 1, None).await });
+
+        timeout(Duration::from_secs(5), async move {
+            let (mut conn, _) = accept_handshake(&server).await;
+
+            let echo_req = Packet::request(Request::EchoReq {
+                data: b"heartbeat".to_vec(),
+            })
+            .unwrap()
+            .to_bytes()
+            .unwrap();
+            let mut buf = vec![0_u8; echo_req.len()];
+            conn.read_exact(&mut buf).await.unwrap();
+            assert_eq!(buf, echo_req);
+
+            // Stay silent, so the worker gives up on this connection.
+            accept_handshake(&server).await;
+        })
+        .await
+        .expect("worker did not reconnect after a missed heartbeat");
+    }
+
+    #[async_std::test]
+    async fn worker_backs_off_from_flapping_server() {
+        let server = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut state = state(&server).await;
+        // Always jitter to the top of the range, so the delays are predictable.
+        state.rng = Mutex::new(Box::new(StepRng::new(u64::MAX, 0)));
+        spawn(async move { state.worker(&["supertest"], "/usr/bin/true", 1, None).await });
+
+        let accepted = timeout(Duration::from_secs(5), async move {
+            let mut accepted = Vec::new();
+            for _ in 0..4 {
+                // Dropping the connection right away closes it.
+                accept_handshake(&server).await;
+                accepted.push(Instant::now());
+            }
+            accepted
+        })
+        .await
+        .expect("worker did not reconnect");
+
+        let gaps: Vec<Duration> = accepted.windows(2).map(|w| w[1] - w[0]).collect();
+        assert!(gaps[1] >= Duration::from_millis(20), "{:?}", gaps);
+        assert!(gaps[2] >= Duration::from_millis(40), "{:?}", gaps);
+    }
+
+    #[async_std::test]
+    async fn backoff_doubles_up_to_cap() {
+        let server = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let state = state(&server).await;
+        assert_eq!(state.backoff_ceiling(0), Duration::from_millis(10));
+        assert_eq!(state.backoff_ceiling(1), Duration::from_millis(20));
+        assert_eq!(state.backoff_ceiling(2), Duration::from_millis(40));
+        assert_eq!(state.backoff_ceiling(3), Duration::from_millis(40));
+        assert_eq!(state.backoff_ceiling(100), Duration::from_millis(40));
+    }
+
+    #[async_std::test]
+    async fn backoff_jitters_under_ceiling() {
+        let server = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut state = state(&server).await;
+        state.rng = Mutex::new(Box::new(StdRng::seed_from_u64(129)));
+
+        for attempt in 0..5 {
+            let ceiling = state.backoff_ceiling(attempt);
+            let delays: Vec<Duration> = (0..1000).map(|_| state.backoff(attempt)).collect();
+            assert!(delays.iter().all(|d| *d <= ceiling), "{:?}", delays);
+            // Spread over the whole range, rather than bunched at one end.
+            assert!(delays.iter().any(|d| *d < ceiling / 4), "{:?}", delays);
+            assert!(delays.iter().any(|d| *d > ceiling * 3 / 4), "{:?}", delays);
+        }
+    }
+
+    #[async_std::test]
+    async fn worker_can_do_every_name() {
+        let server = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let state = state(&server).await;
+        spawn(async move {
+            state
+                .worker(&["super::one", "super::two"], "/usr/bin/true", 1, None)
+                .await
+        });
+
+        let (_, handshake) = timeout(Duration::from_secs(5), async move {
+            accept_handshake(&server).await
+        })
+        .await
+        .expect("worker did not connect");
+
+        let reset = Packet::request(Request::ResetAbilities)
+            .unwrap()
+            .to_bytes()
+            .unwrap();
+        let reset_at = handshake
+            .windows(reset.len())
+            .position(|w| w == reset.as_slice())
+            .expect("worker did not reset its abilities");
+
+        for name in &["super::one", "super::two"] {
+            let can_do = Packet::request(Request::CanDo {
+                name: name.as_bytes().to_vec(),
+            })
+            .unwrap()
+            .to_bytes()
+            .unwrap();
+            let can_do_at = handshake
+                .windows(can_do.len())
+                .position(|w| w == can_do.as_slice())
+                .expect("worker did not CAN_DO every name");
+            assert!(reset_at < can_do_at);
+        }
+    }
+
+    #[async_std::test]
+    async fn worker_can_do_timeout() {
+        let server = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut state = state(&server).await;
+        state.use_server_timeout = true;
+        spawn(async move {
+            state
+                .worker(
+                    &["supertest"],
+                    "/usr/bin/true",
+                    1,
+                    Some(Duration::from_secs(90)),
+                )
+                .await
+        });
+
+        let (_, handshake) = timeout(Duration::from_secs(5), async move {
+            accept_handshake(&server).await
+        })
+        .await
+        .expect("worker did not connect");
+
+        let can_do_timeout = Packet::request(Request::CanDoTimeout {
+            name: b"supertest".to_vec(),
+            timeout: b"90".to_vec(),
+        })
+        .unwrap()
+        .to_bytes()
+        .unwrap();
+        assert!(handshake
+            .windows(can_do_timeout.len())
+            .any(|w| w == can_do_timeout.as_slice()));
+    }
+
+    #[async_std::test]
+    async fn worker_verifies_client_id() {
+        let server = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut state = state(&server).await;
+        state.verify_client_id = true;
+        let client_id = state.base_id.clone() + "::supertest=1";
+        spawn(async move { state.worker(&["supertest"], "/usr/bin/true", 1, None).await });
+
+        timeout(Duration::from_secs(5), async move {
+            let (mut conn, _) = server.accept().await.unwrap();
+            let mut set_client_id = Packet::request(Request::SetClientId {
+                id: client_id.as_bytes().to_vec(),
+            })
+            .unwrap()
+            .to_bytes()
+            .unwrap();
+            let echo_req = Packet::request(Request::EchoReq {
+                data: client_id.as_bytes().to_vec(),
+            })
+            .unwrap()
+            .to_bytes()
+            .unwrap();
+            set_client_id.extend(&echo_req);
+
+            let mut buf = vec![0_u8; set_client_id.len()];
+            conn.read_exact(&mut buf).await.unwrap();
+            assert_eq!(buf, set_client_id);
+
+            let echo_res = Packet::response(Response::EchoRes {
+                data: client_id.as_bytes().to_vec(),
+            })
+            .unwrap()
+            .to_bytes()
+            .unwrap();
+            conn.write_all(&echo_res).await.unwrap();
+
+            let mut rest = Vec::new();
+            let mut buf = [0_u8; 1024];
+            while !rest.ends_with(PRE_SLEEP) {
+                let len = conn.read(&mut buf).await.unwrap();
+                assert_ne!(len, 0, "worker hung up after echo");
+                rest.extend(&buf[0..len]);
+            }
+        })
+        .await
+        .expect("worker did not finish its handshake after the echo");
+    }
+
+    #[async_std::test]
+    async fn server_status_over_admin() {
+        let server = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let state = state(&server).await;
+        spawn(async move {
+            let (mut conn, _) = server.accept().await.unwrap();
+            let mut buf = [0_u8; 7];
+            conn.read_exact(&mut buf).await.unwrap();
+            assert_eq!(&buf, b"status\n");
+            conn.write_all(b"Test::a\t1\t2\t3\nTest::b\t0\t0\t1\n.\n")
+                .await
+                .unwrap();
+        });
+
+        let status = timeout(Duration::from_secs(5), state.server_status())
+            .await
+            .expect("status query hung")
+            .unwrap();
+        assert_eq!(
+            status,
+            vec![
+                FunctionStatus {
+                    name: "Test::a".into(),
+                    queued: 1,
+                    running: 2,
+                    workers: 3,
+                },
+                FunctionStatus {
+                    name: "Test::b".into(),
+                    queued: 0,
+                    running: 0,
+                    workers: 1,
+                },
+            ]
+        );
+    }
+}
@@ -1,4 +1,7 @@
+#![allow(clippy::manual_div_ceil)] // in deku-generated code
+
 use deku::prelude::*;
+use std::fmt;
 
 #[derive(Clone, Debug, Eq, PartialEq, DekuRead, DekuWrite)]
 pub struct Packet {
@@ -11,11 +14,7 @@ pub struct Packet {
         update = "self.request.as_ref().map(|r| r.id()).or_else(|| self.response.as_ref().map(|r| r.id())).expect(\"either request or response must be provided\")"
     )]
     kind: u32,
-    #[deku(
-        bytes = 4,
-        endian = "big",
-        update = "self.request.as_ref().map(|r| r.bytes()).or_else(|| self.response.as_ref().map(|r| r.bytes())).expect(\"either request or response must be provided\")"
-    )]
+    #[deku(bytes = 4, endian = "big", update = "self.body_bytes()?")]
     length: usize,
     #[deku(cond = "*magic == PacketMagic::Request", ctx = "*length, *kind")]
     request: Option<Request>,
@@ -23,7 +22,20 @@ pub struct Packet {
     response: Option<Response>,
 }
 
+/// Size of the magic, kind, and length fields that start every packet.
+pub const HEADER_SIZE: usize = 12;
+
 impl Packet {
+    /// Reads the body length from the header at the start of `buf`, if there's
+    /// a whole header there.
+    ///
+    /// This doesn't check the magic or kind, so can be used to size a buffer
+    /// before a packet can be decoded.
+    pub fn peek_length(buf: &[u8]) -> Option<usize> {
+        buf.get(8..HEADER_SIZE)
+            .map(|len| u32::from_be_bytes([len[0], len[1], len[2], len[3]]) as usize)
+    }
+
     pub fn request(r: Request) -> Result<Self, DekuError> {
         let mut pkt = Self {
             magic: PacketMagic::Request,
@@ -47,6 +59,317 @@ impl Packet {
         pkt.update()?;
         Ok(pkt)
     }
+
+    pub fn as_request(&self) -> Option<&Request> {
+        self.request.as_ref()
+    }
+
+    pub fn as_response(&self) -> Option<&Response> {
+        self.response.as_ref()
+    }
+
+    pub fn kind(&self) -> PacketKind {
+        match (&self.request, &self.response) {
+            (Some(r), None) => r.kind(),
+            (None, Some(r)) => r.kind(),
+            _ => unreachable!("EITHER request or response must be provided"),
+        }
+    }
+
+    pub fn is_request(&self) -> bool {
+        self.magic == PacketMagic::Request
+    }
+
+    pub fn is_response(&self) -> bool {
+        self.magic == PacketMagic::Response
+    }
+
+    fn body_bytes(&self) -> Result<usize, DekuError> {
+        check_length(match (&self.request, &self.response) {
+            (Some(r), None) => r.bytes()?,
+            (None, Some(r)) => r.bytes()?,
+            _ => unreachable!("EITHER request or response must be provided"),
+        })
+    }
+}
+
+impl fmt::Display for Packet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (&self.request, &self.response) {
+            (Some(r), None) => write!(f, "{}", r),
+            (None, Some(r)) => write!(f, "{}", r),
+            _ => unreachable!("EITHER request or response must be provided"),
+        }
+    }
+}
+
+/// Splits a byte stream into packets.
+///
+/// Bytes are pushed in as they arrive, in whatever chunks the socket gives,
+/// and packets are taken out once their whole frame is buffered. The header's
+/// length field decides that, so a partial packet is never handed to deku.
+#[derive(Clone, Debug)]
+pub struct PacketDecoder {
+    buf: Vec<u8>,
+    max_packet_size: usize,
+}
+
+impl PacketDecoder {
+    /// A decoder that errors on any packet body larger than `max_packet_size`.
+    pub fn new(max_packet_size: usize) -> Self {
+        Self {
+            buf: Vec::with_capacity(1024),
+            max_packet_size,
+        }
+    }
+
+    pub fn push(&mut self, bytes: &[u8]) {
+        self.buf.extend_from_slice(bytes);
+    }
+
+    /// How many bytes are buffered but not yet decoded.
+    pub fn buffered(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// Drops bytes from the front of the buffer until it starts with something
+    /// that could be a packet magic, and returns how many were dropped.
+    ///
+    /// The length of a frame with a bad magic can't be trusted, so this is the
+    /// only way to find where the next packet starts.
+    fn resync(&mut self) -> usize {
+        let skip = (1..self.buf.len())
+            .find(|&i| could_be_magic(&self.buf[i..]))
+            .unwrap_or(self.buf.len());
+        self.buf.drain(..skip);
+        skip
+    }
+}
+
+/// Why [`PacketDecoder`] couldn't produce a packet.
+#[derive(Debug)]
+pub enum DecodeError {
+    /// The next packet's header announces a body over the size limit.
+    ///
+    /// This is sticky: the frame isn't consumed, so every later `next()`
+    /// returns it again. The only way on is to drop the connection.
+    TooLarge { len: usize, max: usize },
+    /// Bytes that aren't a valid packet. They've been skipped, so decoding
+    /// can carry on.
+    Malformed(DekuError),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::TooLarge { len, max } => {
+                write!(f, "packet of {} bytes is over the {} byte limit", len, max)
+            }
+            Self::Malformed(err) => write!(f, "malformed packet: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::TooLarge { .. } => None,
+            Self::Malformed(err) => Some(err),
+        }
+    }
+}
+
+/// Whether `buf` starts with a packet magic, or with the start of one.
+fn could_be_magic(buf: &[u8]) -> bool {
+    let head = &buf[..buf.len().min(4)];
+    b"\0REQ".starts_with(head) || b"\0RES".starts_with(head)
+}
+
+impl Iterator for PacketDecoder {
+    type Item = Result<Packet, DecodeError>;
+
+    /// Decodes the next buffered packet, or returns `None` until one has fully
+    /// arrived.
+    ///
+    /// A frame that fails to decode is still consumed, so the error can be
+    /// logged and the stream carries on from the next packet. If the magic
+    /// itself is wrong, bytes are skipped up to the next thing that looks like
+    /// a magic instead.
+    fn next(&mut self) -> Option<Self::Item> {
+        if !could_be_magic(&self.buf) {
+            let skipped = self.resync();
+            return Some(Err(DecodeError::Malformed(DekuError::Parse(format!(
+                "bad packet magic, skipped {} bytes",
+                skipped
+            )))));
+        }
+
+        let length = Packet::peek_length(&self.buf)?;
+        if length > self.max_packet_size {
+            return Some(Err(DecodeError::TooLarge {
+                len: length,
+                max: self.max_packet_size,
+            }));
+        }
+
+        let end = HEADER_SIZE + length;
+        if self.buf.len() < end {
+            return None;
+        }
+
+        let frame: Vec<u8> = self.buf.drain(..end).collect();
+        Some(match Packet::from_bytes((&frame, 0)) {
+            Ok(((rest, _), _)) if !rest.is_empty() => Err(DecodeError::Malformed(
+                DekuError::Parse(format!("{} bytes left over after packet body", rest.len())),
+            )),
+            Ok((_, pkt)) => Ok(pkt),
+            Err(err) => Err(DecodeError::Malformed(err)),
+        })
+    }
+}
+
+/// Checks that a body length fits in the 4-byte length field.
+///
+/// Deku would otherwise write only the low 32 bits and produce a corrupt frame,
+/// so this effectively caps a single packet's payload at 4 GiB.
+fn check_length(len: usize) -> Result<usize, DekuError> {
+    if len > u32::MAX as usize {
+        Err(DekuError::InvalidParam(format!(
+            "packet body of {} bytes is over the 4 GiB limit",
+            len
+        )))
+    } else {
+        Ok(len)
+    }
+}
+
+/// Works out how much of a body is left for its trailing field, after the
+/// NUL-terminated fields before it took `used` bytes.
+///
+/// Those fields are read up to a NUL rather than within the body's length, so
+/// a bad length header can make them run past the end; that's a parse error.
+fn remaining(datalen: usize, used: usize) -> Result<usize, DekuError> {
+    datalen.checked_sub(used).ok_or_else(|| {
+        DekuError::Parse(format!(
+            "fields take {} bytes of a {} byte packet body",
+            used, datalen
+        ))
+    })
+}
+
+/// Drops the NUL terminator that `until` leaves on the end of a field.
+///
+/// Fields are kept without it so callers never see wire framing, and the
+/// writer below adds it back.
+fn strip_nul(mut field: Vec<u8>) -> Result<Vec<u8>, DekuError> {
+    if field.last() == Some(&0) {
+        field.pop();
+    }
+    Ok(field)
+}
+
+/// Writes a field followed by its NUL terminator.
+///
+/// Handles and names come from the network, and a NUL inside one would shift
+/// every following field when the packet is read back, so this errors instead.
+fn write_nul_terminated(field: &[u8], output: &mut BitVec<Msb0, u8>) -> Result<(), DekuError> {
+    if let Some(n) = field.iter().position(|b| *b == 0) {
+        return Err(DekuError::InvalidParam(format!(
+            "interior NUL at byte {} in field {:?}",
+            n,
+            String::from_utf8_lossy(field)
+        )));
+    }
+
+    for b in field {
+        b.write(output, ())?;
+    }
+    0_u8.write(output, ())
+}
+
+/// The type of a packet, without its data.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum PacketKind {
+    SetClientId,
+    CanDo,
+    CantDo,
+    CanDoTimeout,
+    ResetAbilities,
+    AllYours,
+    EchoReq,
+    PreSleep,
+    GrabJobUniq,
+    SubmitJob,
+    SubmitJobBg,
+    SubmitJobHighBg,
+    SubmitJobLowBg,
+    WorkStatus,
+    WorkComplete,
+    WorkFail,
+    WorkException,
+    WorkData,
+    WorkWarning,
+    Noop,
+    NoJob,
+    JobAssignUniq,
+    EchoRes,
+    Error,
+    OptionRes,
+    JobCreated,
+    Unknown(u32),
+}
+
+impl fmt::Display for PacketKind {
+    /// Writes the name the protocol documentation uses, e.g. `JOB_ASSIGN_UNIQ`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Self::SetClientId => "SET_CLIENT_ID",
+            Self::CanDo => "CAN_DO",
+            Self::CantDo => "CANT_DO",
+            Self::CanDoTimeout => "CAN_DO_TIMEOUT",
+            Self::ResetAbilities => "RESET_ABILITIES",
+            Self::AllYours => "ALL_YOURS",
+            Self::EchoReq => "ECHO_REQ",
+            Self::PreSleep => "PRE_SLEEP",
+            Self::GrabJobUniq => "GRAB_JOB_UNIQ",
+            Self::SubmitJob => "SUBMIT_JOB",
+            Self::SubmitJobBg => "SUBMIT_JOB_BG",
+            Self::SubmitJobHighBg => "SUBMIT_JOB_HIGH_BG",
+            Self::SubmitJobLowBg => "SUBMIT_JOB_LOW_BG",
+            Self::WorkStatus => "WORK_STATUS",
+            Self::WorkComplete => "WORK_COMPLETE",
+            Self::WorkFail => "WORK_FAIL",
+            Self::WorkException => "WORK_EXCEPTION",
+            Self::WorkData => "WORK_DATA",
+            Self::WorkWarning => "WORK_WARNING",
+            Self::Noop => "NOOP",
+            Self::NoJob => "NO_JOB",
+            Self::JobAssignUniq => "JOB_ASSIGN_UNIQ",
+            Self::EchoRes => "ECHO_RES",
+            Self::Error => "ERROR",
+            Self::OptionRes => "OPTION_RES",
+            Self::JobCreated => "JOB_CREATED",
+            Self::Unknown(kind) => return write!(f, "UNKNOWN({})", kind),
+        };
+        f.write_str(name)
+    }
+}
+
+/// Shows a handle as hex, as they're opaque and often not printable.
+struct Hex<'a>(&'a [u8]);
+
+impl fmt::Display for Hex<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for b in self.0 {
+            write!(f, "{:02x}", b)?;
+        }
+        Ok(())
+    }
+}
+
+/// Shows a name or text field as a quoted, escaped string.
+fn text(field: &[u8]) -> String {
+    format!("{:?}", String::from_utf8_lossy(field))
 }
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq, DekuRead, DekuWrite)]
@@ -76,24 +399,125 @@ pub enum Request {
         #[deku(count = "datalen")]
         name: Vec<u8>,
     },
+    #[deku(id = "23")]
+    CanDoTimeout {
+        #[deku(
+            until = "|v: &u8| *v == 0",
+            map = "strip_nul",
+            writer = "write_nul_terminated(name, output)"
+        )]
+        name: Vec<u8>,
+        /// In seconds, as decimal text.
+        #[deku(count = "remaining(datalen, name.len() + 1)?")]
+        timeout: Vec<u8>,
+    },
+    #[deku(id = "3")]
+    ResetAbilities,
+    #[deku(id = "24")]
+    AllYours,
+    #[deku(id = "16")]
+    EchoReq {
+        #[deku(count = "datalen")]
+        data: Vec<u8>,
+    },
     #[deku(id = "4")]
     PreSleep,
     #[deku(id = "30")]
     GrabJobUniq,
+    #[deku(id = "7")]
+    SubmitJob {
+        #[deku(
+            until = "|v: &u8| *v == 0",
+            map = "strip_nul",
+            writer = "write_nul_terminated(name, output)"
+        )]
+        name: Vec<u8>,
+        #[deku(
+            until = "|v: &u8| *v == 0",
+            map = "strip_nul",
+            writer = "write_nul_terminated(unique, output)"
+        )]
+        unique: Vec<u8>,
+        #[deku(count = "remaining(datalen, name.len() + unique.len() + 2)?")]
+        data: Vec<u8>,
+    },
+    #[deku(id = "18")]
+    SubmitJobBg {
+        #[deku(
+            until = "|v: &u8| *v == 0",
+            map = "strip_nul",
+            writer = "write_nul_terminated(name, output)"
+        )]
+        name: Vec<u8>,
+        #[deku(
+            until = "|v: &u8| *v == 0",
+            map = "strip_nul",
+            writer = "write_nul_terminated(unique, output)"
+        )]
+        unique: Vec<u8>,
+        #[deku(count = "remaining(datalen, name.len() + unique.len() + 2)?")]
+        data: Vec<u8>,
+    },
+    #[deku(id = "32")]
+    SubmitJobHighBg {
+        #[deku(
+            until = "|v: &u8| *v == 0",
+            map = "strip_nul",
+            writer = "write_nul_terminated(name, output)"
+        )]
+        name: Vec<u8>,
+        #[deku(
+            until = "|v: &u8| *v == 0",
+            map = "strip_nul",
+            writer = "write_nul_terminated(unique, output)"
+        )]
+        unique: Vec<u8>,
+        #[deku(count = "remaining(datalen, name.len() + unique.len() + 2)?")]
+        data: Vec<u8>,
+    },
+    #[deku(id = "34")]
+    SubmitJobLowBg {
+        #[deku(
+            until = "|v: &u8| *v == 0",
+            map = "strip_nul",
+            writer = "write_nul_terminated(name, output)"
+        )]
+        name: Vec<u8>,
+        #[deku(
+            until = "|v: &u8| *v == 0",
+            map = "strip_nul",
+            writer = "write_nul_terminated(unique, output)"
+        )]
+        unique: Vec<u8>,
+        #[deku(count = "remaining(datalen, name.len() + unique.len() + 2)?")]
+        data: Vec<u8>,
+    },
     #[deku(id = "12")]
     WorkStatus {
-        #[deku(until = "|v: &u8| *v == 0")]
+        #[deku(
+            until = "|v: &u8| *v == 0",
+            map = "strip_nul",
+            writer = "write_nul_terminated(handle, output)"
+        )]
         handle: Vec<u8>,
-        #[deku(until = "|v: &u8| *v == 0")]
+        #[deku(
+            until = "|v: &u8| *v == 0",
+            map = "strip_nul",
+            writer = "write_nul_terminated(numerator, output)"
+        )]
         numerator: Vec<u8>,
-        #[deku(count = "datalen - (handle.len() + numerator.len())")]
+        #[deku(count = "remaining(datalen, handle.len() + numerator.len() + 2)?")]
         denominator: Vec<u8>,
     },
     #[deku(id = "13")]
     WorkComplete {
-        #[deku(until = "|v: &u8| *v == 0")]
+        #[deku(
+            until = "|v: &u8| *v == 0",
+            map = "strip_nul",
+            writer = "write_nul_terminated(handle, output)"
+        )]
         handle: Vec<u8>,
-        #[deku(count = "datalen - handle.len()")]
+        #[deku(count = "remaining(datalen, handle.len() + 1)?")]
         data: Vec<u8>,
     },
     #[deku(id = "14")]
@@ -103,16 +527,42 @@ pub enum Request {
     },
     #[deku(id = "25")]
     WorkException {
-        #[deku(until = "|v: &u8| *v == 0")]
+        #[deku(
+            until = "|v: &u8| *v == 0",
+            map = "strip_nul",
+            writer = "write_nul_terminated(handle, output)"
+        )]
         handle: Vec<u8>,
-        #[deku(count = "datalen - handle.len()")]
+        #[deku(count = "remaining(datalen, handle.len() + 1)?")]
         data: Vec<u8>,
     },
     #[deku(id = "28")]
     WorkData {
-        #[deku(until = "|v: &u8| *v == 0")]
+        #[deku(
+            until = "|v: &u8| *v == 0",
+            map = "strip_nul",
+            writer = "write_nul_terminated(handle, output)"
+        )]
+        handle: Vec<u8>,
+        #[deku(count = "remaining(datalen, handle.len() + 1)?")]
+        data: Vec<u8>,
+    },
+    #[deku(id = "29")]
+    WorkWarning {
+        #[deku(
+            until = "|v: &u8| *v == 0",
+            map = "strip_nul",
+            writer = "write_nul_terminated(handle, output)"
+        )]
         handle: Vec<u8>,
-        #[deku(count = "datalen - handle.len()")]
+        #[deku(count = "remaining(datalen, handle.len() + 1)?")]
+        data: Vec<u8>,
+    },
+    /// Any request we don't know about, kept whole so the stream stays framed.
+    Unknown {
+        #[deku(skip, default = "kind")]
+        kind: u32,
+        #[deku(count = "datalen")]
         data: Vec<u8>,
     },
 }
@@ -123,20 +573,55 @@ impl Request {
             Self::SetClientId { .. } => 22,
             Self::CanDo { .. } => 1,
             Self::CantDo { .. } => 2,
+            Self::CanDoTimeout { .. } => 23,
+            Self::ResetAbilities => 3,
+            Self::AllYours => 24,
+            Self::EchoReq { .. } => 16,
             Self::PreSleep => 4,
             Self::GrabJobUniq => 30,
+            Self::SubmitJob { .. } => 7,
+            Self::SubmitJobBg { .. } => 18,
+            Self::SubmitJobHighBg { .. } => 32,
+            Self::SubmitJobLowBg { .. } => 34,
             Self::WorkStatus { .. } => 12,
             Self::WorkComplete { .. } => 13,
             Self::WorkFail { .. } => 14,
             Self::WorkException { .. } => 25,
             Self::WorkData { .. } => 28,
+            Self::WorkWarning { .. } => 29,
+            Self::Unknown { kind, .. } => *kind,
+        }
+    }
+
+    pub(crate) fn kind(&self) -> PacketKind {
+        match self {
+            Self::SetClientId { .. } => PacketKind::SetClientId,
+            Self::CanDo { .. } => PacketKind::CanDo,
+            Self::CantDo { .. } => PacketKind::CantDo,
+            Self::CanDoTimeout { .. } => PacketKind::CanDoTimeout,
+            Self::ResetAbilities => PacketKind::ResetAbilities,
+            Self::AllYours => PacketKind::AllYours,
+            Self::EchoReq { .. } => PacketKind::EchoReq,
+            Self::PreSleep => PacketKind::PreSleep,
+            Self::GrabJobUniq => PacketKind::GrabJobUniq,
+            Self::SubmitJob { .. } => PacketKind::SubmitJob,
+            Self::SubmitJobBg { .. } => PacketKind::SubmitJobBg,
+            Self::SubmitJobHighBg { .. } => PacketKind::SubmitJobHighBg,
+            Self::SubmitJobLowBg { .. } => PacketKind::SubmitJobLowBg,
+            Self::WorkStatus { .. } => PacketKind::WorkStatus,
+            Self::WorkComplete { .. } => PacketKind::WorkComplete,
+            Self::WorkFail { .. } => PacketKind::WorkFail,
+            Self::WorkException { .. } => PacketKind::WorkException,
+            Self::WorkData { .. } => PacketKind::WorkData,
+            Self::WorkWarning { .. } => PacketKind::WorkWarning,
+            Self::Unknown { kind, .. } => PacketKind::Unknown(*kind),
         }
     }
 
-    pub(crate) fn bytes(&self) -> usize {
+    pub(crate) fn bytes(&self) -> Result<usize, DekuError> {
         let mut buf = BitVec::new();
-        self.write(&mut buf, (0, 0)).unwrap();
-        buf.len() / 8
+        self.write(&mut buf, (0, 0))?;
+        Ok(buf.len() / 8)
     }
 }
 
@@ -149,15 +634,60 @@ pub enum Response {
     NoJob,
     #[deku(id = "31")]
     JobAssignUniq {
-        #[deku(until = "|v: &u8| *v == 0")]
+        #[deku(
+            until = "|v: &u8| *v == 0",
+            map = "strip_nul",
+            writer = "write_nul_terminated(handle, output)"
+        )]
         handle: Vec<u8>,
-        #[deku(until = "|v: &u8| *v == 0")]
+        #[deku(
+            until = "|v: &u8| *v == 0",
+            map = "strip_nul",
+            writer = "write_nul_terminated(name, output)"
+        )]
         name: Vec<u8>,
-        #[deku(until = "|v: &u8| *v == 0")]
+        #[deku(
+            until = "|v: &u8| *v == 0",
+            map = "strip_nul",
+            writer = "write_nul_terminated(unique, output)"
+        )]
         unique: Vec<u8>,
-        #[deku(count = "datalen - (handle.len() + name.len() + unique.len())")]
+        #[deku(count = "remaining(datalen, handle.len() + name.len() + unique.len() + 3)?")]
         workload: Vec<u8>,
     },
+    #[deku(id = "17")]
+    EchoRes {
+        #[deku(count = "datalen")]
+        data: Vec<u8>,
+    },
+    #[deku(id = "19")]
+    Error {
+        #[deku(
+            until = "|v: &u8| *v == 0",
+            map = "strip_nul",
+            writer = "write_nul_terminated(code, output)"
+        )]
+        code: Vec<u8>,
+        #[deku(count = "remaining(datalen, code.len() + 1)?")]
+        text: Vec<u8>,
+    },
+    #[deku(id = "26")]
+    OptionRes {
+        #[deku(count = "datalen")]
+        name: Vec<u8>,
+    },
+    #[deku(id = "8")]
+    JobCreated {
+        #[deku(count = "datalen")]
+        handle: Vec<u8>,
+    },
+    /// Any response we don't know about, kept whole so the stream stays framed.
+    Unknown {
+        #[deku(skip, default = "kind")]
+        kind: u32,
+        #[deku(count = "datalen")]
+        data: Vec<u8>,
+    },
 }
 
 impl Response {
@@ -166,20 +696,126 @@ impl Response {
             Self::Noop => 6,
             Self::NoJob => 10,
             Self::JobAssignUniq { .. } => 31,
+            Self::EchoRes { .. } => 17,
+            Self::Error { .. } => 19,
+            Self::OptionRes { .. } => 26,
+            Self::JobCreated { .. } => 8,
+            Self::Unknown { kind, .. } => *kind,
+        }
+    }
+
+    pub(crate) fn kind(&self) -> PacketKind {
+        match self {
+            Self::Noop => PacketKind::Noop,
+            Self::NoJob => PacketKind::NoJob,
+            Self::JobAssignUniq { .. } => PacketKind::JobAssignUniq,
+            Self::EchoRes { .. } => PacketKind::EchoRes,
+            Self::Error { .. } => PacketKind::Error,
+            Self::OptionRes { .. } => PacketKind::OptionRes,
+            Self::JobCreated { .. } => PacketKind::JobCreated,
+            Self::Unknown { kind, .. } => PacketKind::Unknown(*kind),
         }
     }
 
-    pub(crate) fn bytes(&self) -> usize {
+    pub(crate) fn bytes(&self) -> Result<usize, DekuError> {
         let mut buf = BitVec::new();
-        self.write(&mut buf, (0, 0)).unwrap();
-        buf.len() / 8
+        self.write(&mut buf, (0, 0))?;
+        Ok(buf.len() / 8)
+    }
+}
+
+impl fmt::Display for Request {
+    /// A one-line summary: the packet name, with handles in hex, names as
+    /// text, and payloads as a byte count.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.kind())?;
+        match self {
+            Self::SetClientId { id } => write!(f, " id={}", text(id)),
+            Self::CanDo { name } | Self::CantDo { name } => write!(f, " name={}", text(name)),
+            Self::CanDoTimeout { name, timeout } => write!(
+                f,
+                " name={} timeout={}",
+                text(name),
+                String::from_utf8_lossy(timeout)
+            ),
+            Self::EchoReq { data } => write!(f, " ({} bytes)", data.len()),
+            Self::WorkStatus {
+                handle,
+                numerator,
+                denominator,
+            } => write!(
+                f,
+                " handle={} {}/{}",
+                Hex(handle),
+                String::from_utf8_lossy(numerator),
+                String::from_utf8_lossy(denominator)
+            ),
+            Self::WorkComplete { handle, data }
+            | Self::WorkException { handle, data }
+            | Self::WorkData { handle, data }
+            | Self::WorkWarning { handle, data } => {
+                write!(f, " handle={} ({} bytes)", Hex(handle), data.len())
+            }
+            Self::WorkFail { handle } => write!(f, " handle={}", Hex(handle)),
+            Self::Unknown { data, .. } => write!(f, " ({} bytes)", data.len()),
+            Self::SubmitJob { name, unique, data }
+            | Self::SubmitJobBg { name, unique, data }
+            | Self::SubmitJobHighBg { name, unique, data }
+            | Self::SubmitJobLowBg { name, unique, data } => write!(
+                f,
+                " name={} unique={} ({} bytes)",
+                text(name),
+                text(unique),
+                data.len()
+            ),
+            Self::ResetAbilities | Self::AllYours | Self::PreSleep | Self::GrabJobUniq => Ok(()),
+        }
+    }
+}
+
+impl fmt::Display for Response {
+    /// A one-line summary: the packet name, with handles in hex, names as
+    /// text, and payloads as a byte count.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.kind())?;
+        match self {
+            Self::JobAssignUniq {
+                handle,
+                name,
+                unique,
+                workload,
+            } => write!(
+                f,
+                " handle={} name={} unique={} ({} bytes)",
+                Hex(handle),
+                text(name),
+                text(unique),
+                workload.len()
+            ),
+            Self::EchoRes { data } | Self::Unknown { data, .. } => {
+                write!(f, " ({} bytes)", data.len())
+            }
+            Self::Error {
+                code,
+                text: message,
+            } => {
+                write!(f, " code={} text={}", text(code), text(message))
+            }
+            Self::OptionRes { name } => write!(f, " name={}", text(name)),
+            Self::JobCreated { handle } => write!(f, " handle={}", Hex(handle)),
+            Self::Noop | Self::NoJob => Ok(()),
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{Packet, PacketMagic, Request, Response};
+    use super::{
+        check_length, DecodeError, Packet, PacketDecoder, PacketKind, PacketMagic, Request,
+        Response,
+    };
     use deku::prelude::*;
+    use proptest::{collection::vec, prelude::*};
     use std::ffi::CString;
 
     fn get_bytes<T: DekuContainerWrite>(r: Result<T, DekuError>) -> Vec<u8> {
@@ -204,6 +840,8 @@ mod tests {
         assert_eq!(rest, &[]);
         assert_eq!(pkt.magic, PacketMagic::Response);
         assert_eq!(pkt.response, Some(Response::Noop));
+        assert_eq!(pkt.kind(), PacketKind::Noop);
+        assert!(pkt.is_response());
     }
 
     #[test]
@@ -271,9 +909,9 @@ mod tests {
         assert_eq!(
             pkt.response,
             Some(Response::JobAssignUniq {
-                handle: b"H:localhost:1\0".to_vec(),
-                name: b"gandhy_matlack\0".to_vec(),
-                unique: b"e2cb1f42-1181-476e-960a-2c157ddab8ab\0".to_vec(),
+                handle: b"H:localhost:1".to_vec(),
+                name: b"gandhy_matlack".to_vec(),
+                unique: b"e2cb1f42-1181-476e-960a-2c157ddab8ab".to_vec(),
                 workload: b"[1,2,3]".to_vec(),
             })
         );
@@ -283,9 +921,9 @@ mod tests {
     fn write_response_jobassignuniq() {
         assert_eq!(
             get_bytes(Packet::response(Response::JobAssignUniq {
-                handle: b"H:localhost:2\0".to_vec(),
-                name: b"lahn_ditch\0".to_vec(),
-                unique: b"8fdff463-4e6f-4c6f-8e22-d3b5ea35f6fe\0".to_vec(),
+                handle: b"H:localhost:2".to_vec(),
+                name: b"lahn_ditch".to_vec(),
+                unique: b"8fdff463-4e6f-4c6f-8e22-d3b5ea35f6fe".to_vec(),
                 workload: b"[9,8,7]".to_vec(),
             })),
             response_jobassignuniq(
@@ -297,6 +935,19 @@ mod tests {
         );
     }
 
+    #[test]
+    fn roundtrip_response_jobassignuniq() {
+        let data = response_jobassignuniq(
+            "H:localhost:3",
+            "gandhy_matlack",
+            b"0b7fdfa5-3a52-4be8-a19c-53e0e34e2fa1",
+            b"\0[1,2,3]\0",
+        );
+        let ((rest, _), pkt) = Packet::from_bytes((&data, 0)).unwrap();
+        assert_eq!(rest, &[]);
+        assert_eq!(pkt.to_bytes().unwrap(), data);
+    }
+
     fn request_cando(name: &str) -> Vec<u8> {
         let bname = name.as_bytes();
         let mut data: Vec<u8> = Vec::new();
@@ -319,6 +970,8 @@ mod tests {
                 name: b"helloworld".to_vec(),
             })
         );
+        assert_eq!(pkt.kind(), PacketKind::CanDo);
+        assert!(pkt.is_request());
     }
 
     #[test]
@@ -330,4 +983,653 @@ mod tests {
             request_cando("bananasplit")
         );
     }
+
+    fn request_candotimeout(name: &str, timeout: &str) -> Vec<u8> {
+        let bname = CString::new(name).unwrap();
+        let bname = bname.as_bytes_with_nul();
+
+        let mut data: Vec<u8> = Vec::new();
+        data.extend(&MAGIC_REQ.to_be_bytes());
+        data.extend(&23_u32.to_be_bytes());
+        data.extend(&((bname.len() + timeout.len()) as u32).to_be_bytes());
+        data.extend(bname);
+        data.extend(timeout.as_bytes());
+        data
+    }
+
+    #[test]
+    fn write_request_candotimeout() {
+        assert_eq!(
+            get_bytes(Packet::request(Request::CanDoTimeout {
+                name: b"bananasplit".to_vec(),
+                timeout: b"120".to_vec(),
+            })),
+            request_candotimeout("bananasplit", "120")
+        );
+    }
+
+    #[test]
+    fn roundtrip_request_candotimeout() {
+        let data = request_candotimeout("helloworld", "30");
+        let ((rest, _), pkt) = Packet::from_bytes((&data, 0)).unwrap();
+        assert_eq!(rest, &[]);
+        assert_eq!(
+            pkt.request,
+            Some(Request::CanDoTimeout {
+                name: b"helloworld".to_vec(),
+                timeout: b"30".to_vec(),
+            })
+        );
+        assert_eq!(pkt.to_bytes().unwrap(), data);
+    }
+
+    #[test]
+    fn write_request_resetabilities() {
+        let mut data: Vec<u8> = Vec::new();
+        data.extend(&MAGIC_REQ.to_be_bytes());
+        data.extend(&3_u32.to_be_bytes());
+        data.extend(&0_u32.to_be_bytes());
+        assert_eq!(get_bytes(Packet::request(Request::ResetAbilities)), data);
+    }
+
+    fn request_submitjobbg(name: &str, unique: &str, workload: &[u8]) -> Vec<u8> {
+        let bname = CString::new(name).unwrap();
+        let bname = bname.as_bytes_with_nul();
+        let bunique = CString::new(unique).unwrap();
+        let bunique = bunique.as_bytes_with_nul();
+
+        let mut data: Vec<u8> = Vec::new();
+        data.extend(&MAGIC_REQ.to_be_bytes());
+        data.extend(&18_u32.to_be_bytes());
+        data.extend(&((bname.len() + bunique.len() + workload.len()) as u32).to_be_bytes());
+        data.extend(bname);
+        data.extend(bunique);
+        data.extend(workload);
+        data
+    }
+
+    #[test]
+    fn write_request_submitjobbg() {
+        assert_eq!(
+            get_bytes(Packet::request(Request::SubmitJobBg {
+                name: b"reverse".to_vec(),
+                unique: b"".to_vec(),
+                data: b"[1,2,3]".to_vec(),
+            })),
+            request_submitjobbg("reverse", "", b"[1,2,3]")
+        );
+    }
+
+    #[test]
+    fn read_response_jobcreated() {
+        let mut data: Vec<u8> = Vec::new();
+        data.extend(&MAGIC_RES.to_be_bytes());
+        data.extend(&8_u32.to_be_bytes());
+        data.extend(&13_u32.to_be_bytes());
+        data.extend(b"H:localhost:7");
+
+        let ((rest, _), pkt) = Packet::from_bytes((&data, 0)).unwrap();
+        assert_eq!(rest, &[]);
+        assert_eq!(
+            pkt.response,
+            Some(Response::JobCreated {
+                handle: b"H:localhost:7".to_vec()
+            })
+        );
+    }
+
+    fn request_allyours() -> Vec<u8> {
+        let mut data: Vec<u8> = Vec::new();
+        data.extend(&MAGIC_REQ.to_be_bytes());
+        data.extend(&24_u32.to_be_bytes());
+        data.extend(&0_u32.to_be_bytes());
+        data
+    }
+
+    #[test]
+    fn read_request_allyours() {
+        let data = request_allyours();
+        let ((rest, _), pkt) = Packet::from_bytes((&data, 0)).unwrap();
+        assert_eq!(rest, &[]);
+        assert_eq!(pkt.magic, PacketMagic::Request);
+        assert_eq!(pkt.request, Some(Request::AllYours));
+    }
+
+    #[test]
+    fn write_request_allyours() {
+        assert_eq!(
+            get_bytes(Packet::request(Request::AllYours)),
+            request_allyours()
+        );
+    }
+
+    fn request_workwarning(handle: &str, warning: &[u8]) -> Vec<u8> {
+        let bhandle = CString::new(handle).unwrap();
+        let bhandle = bhandle.as_bytes_with_nul();
+
+        let mut data: Vec<u8> = Vec::new();
+        data.extend(&MAGIC_REQ.to_be_bytes());
+        data.extend(&29_u32.to_be_bytes());
+        data.extend(&((bhandle.len() + warning.len()) as u32).to_be_bytes());
+        data.extend(bhandle);
+        data.extend(warning);
+        data
+    }
+
+    #[test]
+    fn read_request_workwarning() {
+        let data = request_workwarning("H:localhost:4", b"{\"slow\":true}");
+        let ((rest, _), pkt) = Packet::from_bytes((&data, 0)).unwrap();
+        assert_eq!(rest, &[]);
+        assert_eq!(pkt.magic, PacketMagic::Request);
+        assert_eq!(
+            pkt.request,
+            Some(Request::WorkWarning {
+                handle: b"H:localhost:4".to_vec(),
+                data: b"{\"slow\":true}".to_vec(),
+            })
+        );
+    }
+
+    #[test]
+    fn write_request_workwarning() {
+        assert_eq!(
+            get_bytes(Packet::request(Request::WorkWarning {
+                handle: b"H:localhost:5".to_vec(),
+                data: b"careful".to_vec(),
+            })),
+            request_workwarning("H:localhost:5", b"careful")
+        );
+    }
+
+    #[test]
+    fn write_request_workcomplete_interior_nul() {
+        assert!(matches!(
+            Packet::request(Request::WorkComplete {
+                handle: b"H:local\0host:3".to_vec(),
+                data: b"[4,5,6]".to_vec(),
+            }),
+            Err(DekuError::InvalidParam(_))
+        ));
+    }
+
+    #[test]
+    fn write_request_workcomplete_trailing_nul() {
+        assert!(matches!(
+            Packet::request(Request::WorkComplete {
+                handle: b"H:localhost:3\0".to_vec(),
+                data: b"[4,5,6]".to_vec(),
+            }),
+            Err(DekuError::InvalidParam(_))
+        ));
+    }
+
+    #[test]
+    fn read_field_past_short_length() {
+        let mut data: Vec<u8> = Vec::new();
+        data.extend(&MAGIC_REQ.to_be_bytes());
+        data.extend(&13_u32.to_be_bytes());
+        data.extend(&0_u32.to_be_bytes());
+        data.extend(b"abc\0");
+        assert!(matches!(
+            Packet::from_bytes((&data, 0)),
+            Err(DekuError::Parse(_))
+        ));
+
+        let mut data = response_jobassignuniq("a", "b", b"c", b"");
+        data[8..12].copy_from_slice(&1_u32.to_be_bytes());
+        assert!(matches!(
+            Packet::from_bytes((&data, 0)),
+            Err(DekuError::Parse(_))
+        ));
+    }
+
+    #[test]
+    fn length_at_limit() {
+        assert_eq!(check_length(u32::MAX as usize), Ok(u32::MAX as usize));
+    }
+
+    #[test]
+    fn length_over_limit() {
+        assert!(matches!(
+            check_length(u32::MAX as usize + 1),
+            Err(DekuError::InvalidParam(_))
+        ));
+    }
+
+    fn request_echoreq(echo: &[u8]) -> Vec<u8> {
+        let mut data: Vec<u8> = Vec::new();
+        data.extend(&MAGIC_REQ.to_be_bytes());
+        data.extend(&16_u32.to_be_bytes());
+        data.extend(&(echo.len() as u32).to_be_bytes());
+        data.extend(echo);
+        data
+    }
+
+    #[test]
+    fn read_request_echoreq() {
+        let data = request_echoreq(b"are you there?");
+        let ((rest, _), pkt) = Packet::from_bytes((&data, 0)).unwrap();
+        assert_eq!(rest, &[]);
+        assert_eq!(pkt.magic, PacketMagic::Request);
+        assert_eq!(
+            pkt.request,
+            Some(Request::EchoReq {
+                data: b"are you there?".to_vec(),
+            })
+        );
+    }
+
+    #[test]
+    fn write_request_echoreq() {
+        assert_eq!(
+            get_bytes(Packet::request(Request::EchoReq {
+                data: b"hello?".to_vec()
+            })),
+            request_echoreq(b"hello?")
+        );
+    }
+
+    fn response_echores(echo: &[u8]) -> Vec<u8> {
+        let mut data: Vec<u8> = Vec::new();
+        data.extend(&MAGIC_RES.to_be_bytes());
+        data.extend(&17_u32.to_be_bytes());
+        data.extend(&(echo.len() as u32).to_be_bytes());
+        data.extend(echo);
+        data
+    }
+
+    #[test]
+    fn read_response_echores() {
+        let data = response_echores(b"are you there?");
+        let ((rest, _), pkt) = Packet::from_bytes((&data, 0)).unwrap();
+        assert_eq!(rest, &[]);
+        assert_eq!(pkt.magic, PacketMagic::Response);
+        assert_eq!(
+            pkt.response,
+            Some(Response::EchoRes {
+                data: b"are you there?".to_vec(),
+            })
+        );
+    }
+
+    #[test]
+    fn write_response_echores() {
+        assert_eq!(
+            get_bytes(Packet::response(Response::EchoRes {
+                data: b"hello?".to_vec()
+            })),
+            response_echores(b"hello?")
+        );
+    }
+
+    fn response_error(code: &str, text: &str) -> Vec<u8> {
+        let bcode = CString::new(code).unwrap();
+        let bcode = bcode.as_bytes_with_nul();
+
+        let mut data: Vec<u8> = Vec::new();
+        data.extend(&MAGIC_RES.to_be_bytes());
+        data.extend(&19_u32.to_be_bytes());
+        data.extend(&((bcode.len() + text.len()) as u32).to_be_bytes());
+        data.extend(bcode);
+        data.extend(text.as_bytes());
+        data
+    }
+
+    #[test]
+    fn read_response_error() {
+        let data = response_error("ERR_UNKNOWN_COMMAND", "Unknown+server+command");
+        let ((rest, _), pkt) = Packet::from_bytes((&data, 0)).unwrap();
+        assert_eq!(rest, &[]);
+        assert_eq!(pkt.magic, PacketMagic::Response);
+        assert_eq!(
+            pkt.response,
+            Some(Response::Error {
+                code: b"ERR_UNKNOWN_COMMAND".to_vec(),
+                text: b"Unknown+server+command".to_vec(),
+            })
+        );
+    }
+
+    #[test]
+    fn write_response_error() {
+        assert_eq!(
+            get_bytes(Packet::response(Response::Error {
+                code: b"ERR_UNKNOWN_COMMAND".to_vec(),
+                text: b"Unknown+server+command".to_vec(),
+            })),
+            response_error("ERR_UNKNOWN_COMMAND", "Unknown+server+command")
+        );
+    }
+
+    fn response_unknown(kind: u32, body: &[u8]) -> Vec<u8> {
+        let mut data: Vec<u8> = Vec::new();
+        data.extend(&MAGIC_RES.to_be_bytes());
+        data.extend(&kind.to_be_bytes());
+        data.extend(&(body.len() as u32).to_be_bytes());
+        data.extend(body);
+        data
+    }
+
+    #[test]
+    fn read_response_unknown() {
+        let data = response_unknown(1234, b"who knows");
+        let ((rest, _), pkt) = Packet::from_bytes((&data, 0)).unwrap();
+        assert_eq!(rest, &[]);
+        assert_eq!(pkt.magic, PacketMagic::Response);
+        assert_eq!(pkt.kind(), PacketKind::Unknown(1234));
+        assert_eq!(
+            pkt.response,
+            Some(Response::Unknown {
+                kind: 1234,
+                data: b"who knows".to_vec(),
+            })
+        );
+    }
+
+    #[test]
+    fn write_response_unknown() {
+        assert_eq!(
+            get_bytes(Packet::response(Response::Unknown {
+                kind: 4321,
+                data: b"still unknown".to_vec(),
+            })),
+            response_unknown(4321, b"still unknown")
+        );
+    }
+
+    #[test]
+    fn read_response_unknown_between_known() {
+        let mut data = response_noop();
+        data.extend(response_unknown(1234, b"\0\0REQ\0RES\0"));
+        data.extend(response_nojob());
+
+        let ((rest, _), pkt) = Packet::from_bytes((&data, 0)).unwrap();
+        assert_eq!(pkt.response, Some(Response::Noop));
+        let ((rest, _), pkt) = Packet::from_bytes((rest, 0)).unwrap();
+        assert_eq!(pkt.kind(), PacketKind::Unknown(1234));
+        let ((rest, _), pkt) = Packet::from_bytes((rest, 0)).unwrap();
+        assert_eq!(pkt.response, Some(Response::NoJob));
+        assert_eq!(rest, &[]);
+    }
+
+    #[test]
+    fn read_request_unknown_between_known() {
+        let mut data = request_cando("before");
+        data.extend(&MAGIC_REQ.to_be_bytes());
+        data.extend(&999_u32.to_be_bytes());
+        data.extend(&3_u32.to_be_bytes());
+        data.extend(b"a\0b");
+        data.extend(request_cando("after"));
+
+        let ((rest, _), pkt) = Packet::from_bytes((&data, 0)).unwrap();
+        assert_eq!(
+            pkt.request,
+            Some(Request::CanDo {
+                name: b"before".to_vec()
+            })
+        );
+        let ((rest, _), pkt) = Packet::from_bytes((rest, 0)).unwrap();
+        assert_eq!(
+            pkt.request,
+            Some(Request::Unknown {
+                kind: 999,
+                data: b"a\0b".to_vec(),
+            })
+        );
+        let ((rest, _), pkt) = Packet::from_bytes((rest, 0)).unwrap();
+        assert_eq!(
+            pkt.request,
+            Some(Request::CanDo {
+                name: b"after".to_vec()
+            })
+        );
+        assert_eq!(rest, &[]);
+    }
+
+    #[test]
+    fn display_request() {
+        assert_eq!(
+            Request::CanDo {
+                name: b"Test::reverse".to_vec()
+            }
+            .to_string(),
+            r#"CAN_DO name="Test::reverse""#
+        );
+        assert_eq!(
+            Request::WorkComplete {
+                handle: b"H:a:1".to_vec(),
+                data: b"[4,5,6]".to_vec(),
+            }
+            .to_string(),
+            "WORK_COMPLETE handle=483a613a31 (7 bytes)"
+        );
+        assert_eq!(Request::PreSleep.to_string(), "PRE_SLEEP");
+    }
+
+    #[test]
+    fn display_response() {
+        assert_eq!(
+            Response::JobAssignUniq {
+                handle: b"H:a:1".to_vec(),
+                name: b"reverse".to_vec(),
+                unique: b"u\t".to_vec(),
+                workload: b"[1,2,3]".to_vec(),
+            }
+            .to_string(),
+            r#"JOB_ASSIGN_UNIQ handle=483a613a31 name="reverse" unique="u\t" (7 bytes)"#
+        );
+        assert_eq!(
+            Response::Unknown {
+                kind: 99,
+                data: b"xyz".to_vec(),
+            }
+            .to_string(),
+            "UNKNOWN(99) (3 bytes)"
+        );
+    }
+
+    #[test]
+    fn decoder_every_split() {
+        let mut data = response_jobassignuniq("H:localhost:1", "a", b"b", b"[1,2,3]");
+        data.extend(response_noop());
+
+        for split in 0..=data.len() {
+            let mut decoder = PacketDecoder::new(1024);
+            let mut packets = Vec::new();
+            for chunk in &[&data[..split], &data[split..]] {
+                decoder.push(chunk);
+                packets.extend(&mut decoder);
+            }
+
+            let packets: Vec<PacketKind> = packets.into_iter().map(|p| p.unwrap().kind()).collect();
+            assert_eq!(
+                packets,
+                vec![PacketKind::JobAssignUniq, PacketKind::Noop],
+                "split at {}",
+                split
+            );
+        }
+    }
+
+    #[test]
+    fn decoder_header_then_body() {
+        let data = response_echores(b"are you there?");
+        let mut decoder = PacketDecoder::new(1024);
+
+        decoder.push(&data[..12]);
+        assert!(decoder.next().is_none());
+
+        decoder.push(&data[12..]);
+        assert_eq!(
+            decoder.next().unwrap().unwrap().as_response(),
+            Some(&Response::EchoRes {
+                data: b"are you there?".to_vec()
+            })
+        );
+        assert!(decoder.next().is_none());
+    }
+
+    #[test]
+    fn decoder_resyncs_after_bad_magic() {
+        let mut decoder = PacketDecoder::new(1024);
+        decoder.push(b"garbage\0RE");
+        decoder.push(&response_noop()[3..]);
+
+        assert!(matches!(
+            decoder.next(),
+            Some(Err(DecodeError::Malformed(DekuError::Parse(_))))
+        ));
+        assert_eq!(decoder.next().unwrap().unwrap().kind(), PacketKind::Noop);
+        assert!(decoder.next().is_none());
+    }
+
+    #[test]
+    fn decoder_over_limit() {
+        let mut decoder = PacketDecoder::new(4);
+        decoder.push(&response_echores(b"hello"));
+        assert!(matches!(
+            decoder.next(),
+            Some(Err(DecodeError::TooLarge { len: 5, max: 4 }))
+        ));
+        // Nothing was consumed, so it keeps failing the same way.
+        assert!(matches!(
+            decoder.next(),
+            Some(Err(DecodeError::TooLarge { len: 5, max: 4 }))
+        ));
+    }
+
+    #[test]
+    fn decoder_skips_bad_frame() {
+        let mut decoder = PacketDecoder::new(1024);
+        let mut data = response_error("ERR", "");
+        // Drop the code's NUL so it runs past the end of the frame.
+        data[8..12].copy_from_slice(&3_u32.to_be_bytes());
+        data.pop();
+        decoder.push(&data);
+        decoder.push(&response_nojob());
+
+        assert!(matches!(
+            decoder.next(),
+            Some(Err(DecodeError::Malformed(_)))
+        ));
+        assert_eq!(decoder.next().unwrap().unwrap().kind(), PacketKind::NoJob);
+        assert!(decoder.next().is_none());
+    }
+
+    #[test]
+    fn peek_length() {
+        let data = response_jobassignuniq("H:localhost:1", "a", b"b", b"[1,2,3]");
+        assert_eq!(Packet::peek_length(&data), Some(data.len() - 12));
+        assert_eq!(Packet::peek_length(&data[0..12]), Some(data.len() - 12));
+        assert_eq!(Packet::peek_length(&data[0..11]), None);
+    }
+
+    /// Bytes for a NUL-terminated field, which can't contain a NUL itself.
+    fn field() -> impl Strategy<Value = Vec<u8>> {
+        vec(1_u8.., 0..32)
+    }
+
+    /// Bytes for a trailing field, which runs to the end of the packet.
+    fn data() -> impl Strategy<Value = Vec<u8>> {
+        vec(any::<u8>(), 0..64)
+    }
+
+    fn unknown_kind(known: impl Fn(u32) -> bool) -> impl Strategy<Value = u32> {
+        any::<u32>().prop_filter("known kind", move |k| !known(*k))
+    }
+
+    fn any_request() -> impl Strategy<Value = Request> {
+        prop_oneof![
+            data().prop_map(|id| Request::SetClientId { id }),
+            data().prop_map(|name| Request::CanDo { name }),
+            data().prop_map(|name| Request::CantDo { name }),
+            (field(), data()).prop_map(|(name, timeout)| Request::CanDoTimeout { name, timeout }),
+            Just(Request::ResetAbilities),
+            Just(Request::AllYours),
+            data().prop_map(|data| Request::EchoReq { data }),
+            Just(Request::PreSleep),
+            Just(Request::GrabJobUniq),
+            (field(), field(), data()).prop_map(|(name, unique, data)| Request::SubmitJob {
+                name,
+                unique,
+                data
+            }),
+            (field(), field(), data()).prop_map(|(name, unique, data)| Request::SubmitJobBg {
+                name,
+                unique,
+                data
+            }),
+            (field(), field(), data()).prop_map(|(name, unique, data)| {
+                Request::SubmitJobHighBg { name, unique, data }
+            }),
+            (field(), field(), data()).prop_map(|(name, unique, data)| {
+                Request::SubmitJobLowBg { name, unique, data }
+            }),
+            (field(), field(), data()).prop_map(|(handle, numerator, denominator)| {
+                Request::WorkStatus {
+                    handle,
+                    numerator,
+                    denominator,
+                }
+            }),
+            (field(), data()).prop_map(|(handle, data)| Request::WorkComplete { handle, data }),
+            data().prop_map(|handle| Request::WorkFail { handle }),
+            (field(), data()).prop_map(|(handle, data)| Request::WorkException { handle, data }),
+            (field(), data()).prop_map(|(handle, data)| Request::WorkData { handle, data }),
+            (field(), data()).prop_map(|(handle, data)| Request::WorkWarning { handle, data }),
+            (
+                unknown_kind(|k| [
+                    22, 1, 2, 23, 3, 24, 16, 4, 30, 12, 13, 14, 25, 28, 29, 7, 18, 32, 34
+                ]
+                .contains(&k)),
+                data()
+            )
+                .prop_map(|(kind, data)| Request::Unknown { kind, data }),
+        ]
+    }
+
+    fn any_response() -> impl Strategy<Value = Response> {
+        prop_oneof![
+            Just(Response::Noop),
+            Just(Response::NoJob),
+            (field(), field(), field(), data()).prop_map(|(handle, name, unique, workload)| {
+                Response::JobAssignUniq {
+                    handle,
+                    name,
+                    unique,
+                    workload,
+                }
+            }),
+            data().prop_map(|data| Response::EchoRes { data }),
+            (field(), data()).prop_map(|(code, text)| Response::Error { code, text }),
+            data().prop_map(|name| Response::OptionRes { name }),
+            data().prop_map(|handle| Response::JobCreated { handle }),
+            (
+                unknown_kind(|k| [6, 10, 31, 17, 19, 26, 8].contains(&k)),
+                data()
+            )
+                .prop_map(|(kind, data)| Response::Unknown { kind, data }),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn roundtrip_any_request(r in any_request()) {
+            let pkt = Packet::request(r).unwrap();
+            let bytes = pkt.to_bytes().unwrap();
+            let ((rest, _), read) = Packet::from_bytes((&bytes, 0)).unwrap();
+            prop_assert_eq!(rest, &[]);
+            prop_assert_eq!(read, pkt);
+        }
+
+        #[test]
+        fn roundtrip_any_response(r in any_response()) {
+            let pkt = Packet::response(r).unwrap();
+            let bytes = pkt.to_bytes().unwrap();
+            let ((rest, _), read) = Packet::from_bytes((&bytes, 0)).unwrap();
+            prop_assert_eq!(rest, &[]);
+            prop_assert_eq!(read, pkt);
+        }
+    }
 }